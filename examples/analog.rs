@@ -0,0 +1,22 @@
+//! This is an example program demonstrating reading an Arduino's analog pin A0.
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use arduinors as arduino;
+use arduino::Arduino;
+
+fn main() -> Result<(), arduino::Error> {
+    let board = &arduino::cli::board_list_serial().unwrap()[0];
+
+    let mut arduino = Arduino::connect(board)?;
+
+    for _ in 0..10 {
+        let pin = arduino.read_analog(0)?;
+        println!("A0: {} (of {:?})", pin.value(), pin.valid_values());
+
+        sleep(Duration::from_millis(500));
+    }
+
+    Ok(())
+}
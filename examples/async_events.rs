@@ -0,0 +1,32 @@
+//! This is an example program demonstrating listening for changes of an Arduino's digital pin 2
+//! from async code, while blinking digital pin 13.
+//! It requires the `async` feature: `cargo run --example async_events --features async`.
+
+use std::time::Duration;
+
+use arduinors as arduino;
+use arduino::{AsyncArduino, Pin, PinMode};
+
+#[tokio::main]
+async fn main() -> Result<(), arduino::Error> {
+    let board = &arduino::cli::nonblocking::board_list_serial().await.unwrap()[0];
+
+    let mut arduino = AsyncArduino::connect(board).await?;
+
+    arduino.set_pin_mode(2, PinMode::InputPullup)?;
+    arduino.set_pin_mode(13, PinMode::DigitalOutput)?;
+    let mut events = arduino.subscribe(&[Pin::Digital(2)])?;
+
+    let mut blink = tokio::time::interval(Duration::from_millis(500));
+    let mut led = 0;
+
+    loop {
+        tokio::select! {
+            Some(event) = events.recv() => println!("{:?}: {}", event.pin(), event.value()),
+            _ = blink.tick() => {
+                led = 1 - led;
+                arduino.write(13, led)?;
+            },
+        }
+    }
+}
@@ -9,7 +9,7 @@ use arduino::Arduino;
 fn main() -> Result<(), arduino::Error> {
     let board = &arduino::cli::board_list_serial().unwrap()[0];
 
-    let mut arduino = Arduino::from(board);
+    let mut arduino = Arduino::connect(board)?;
 
     arduino.set_pin_mode(10, arduino::PinMode::DigitalOutput)?;
     arduino.write(10, 1)?;
@@ -0,0 +1,25 @@
+//! This is an example program demonstrating reading a button connected to an Arduino's digital
+//! pin 2.
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use arduinors as arduino;
+use arduino::Arduino;
+
+fn main() -> Result<(), arduino::Error> {
+    let board = &arduino::cli::board_list_serial().unwrap()[0];
+
+    let mut arduino = Arduino::connect(board)?;
+
+    arduino.set_pin_mode(2, arduino::PinMode::InputPullup)?;
+
+    for _ in 0..10 {
+        let pressed = arduino.read_digital(2)? == 0;
+        println!("Button pressed: {}", pressed);
+
+        sleep(Duration::from_millis(500));
+    }
+
+    Ok(())
+}
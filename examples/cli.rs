@@ -15,7 +15,10 @@ fn main() -> Result<(), cli::Error> {
 
     let sketch = Path::new("sketch-path");
 
-    cli::compile(sketch, board)?;
+    for warning in cli::compile(sketch, board)? {
+        println!("{}", warning);
+    }
+
     cli::upload(sketch, board)?;
 
     Ok(())
@@ -0,0 +1,20 @@
+//! This is an example program demonstrating listening for changes of an Arduino's digital pin 2
+//! and analog pin A0.
+
+use arduinors as arduino;
+use arduino::{Arduino, Pin};
+
+fn main() -> Result<(), arduino::Error> {
+    let board = &arduino::cli::board_list_serial().unwrap()[0];
+
+    let mut arduino = Arduino::connect(board)?;
+
+    arduino.set_pin_mode(2, arduino::PinMode::InputPullup)?;
+    let events = arduino.subscribe(&[Pin::Digital(2), Pin::Analog(0)])?;
+
+    for event in events.iter().take(100) {
+        println!("{:?}: {}", event.pin(), event.value());
+    }
+
+    Ok(())
+}
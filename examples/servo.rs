@@ -0,0 +1,24 @@
+//! This is an example program demonstrating sweeping a servo connected to an Arduino's digital
+//! pin 9.
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use arduinors as arduino;
+use arduino::Arduino;
+
+fn main() -> Result<(), arduino::Error> {
+    let board = &arduino::cli::board_list_serial().unwrap()[0];
+
+    let mut arduino = Arduino::connect(board)?;
+    let mut servo = arduino.attach_servo(9, 544, 2400)?;
+
+    for degrees in (0..=180).step_by(10) {
+        servo.set_angle(degrees)?;
+        sleep(Duration::from_millis(100));
+    }
+
+    servo.set_pulse_width(1500)?;
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! This is an example program measuring how many writes per second can be made to a digital pin.
+//! By default a virtual Arduino Mega is used, so the measurement only includes the library's own
+//! overhead. Pass `--hardware` to measure against the first connected Arduino instead.
+
+use std::time::{Duration, Instant};
+
+use arduinors as arduino;
+use arduino::Arduino;
+use arduino::sim::{VirtualBoard, Layout};
+
+const WRITE_COUNT: i32 = 100_000;
+
+fn main() -> Result<(), arduino::Error> {
+    let mut arduino = if std::env::args().any(|arg| arg == "--hardware") {
+        let board = &arduino::cli::board_list_serial().unwrap()[0];
+        Arduino::connect(board)?
+    } else {
+        let board = VirtualBoard::new(Layout::mega());
+        Arduino::connect_transport(board.transport(), Duration::from_secs(1))?
+    };
+
+    arduino.set_pin_mode(13, arduino::PinMode::DigitalOutput)?;
+
+    let start = Instant::now();
+
+    for index in 0..WRITE_COUNT {
+        arduino.write(13, index % 2)?;
+    }
+
+    let elapsed = start.elapsed();
+    let rate = f64::from(WRITE_COUNT) / elapsed.as_secs_f64();
+    println!("{} writes in {:?} ({:.0} writes/s)", WRITE_COUNT, elapsed, rate);
+
+    Ok(())
+}
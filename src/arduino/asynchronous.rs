@@ -0,0 +1,311 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc as async_mpsc, oneshot};
+use tokio_serial::SerialPortBuilderExt;
+
+use crate::Board;
+use crate::arduino::{Arduino, Error, Firmware, DigitalPin, AnalogPin, PinMode, Pin, PinEvent};
+use crate::arduino::{Transport, DEFAULT_HANDSHAKE_TIMEOUT, DEFAULT_READ_TIMEOUT};
+use crate::arduino::transport::{ChannelReader, BAUD_RATE};
+
+/// A handle on an Arduino, for communicating with it via the Firmata protocol from async code.
+///
+/// The Arduino is reached over an async stream (e.g. an async serial port), which is driven by
+/// tasks on the current tokio runtime. Calls which wait for the Arduino do so without blocking the
+/// runtime's threads. Writes never block, as they are queued for the writing task.
+///
+/// The type is only available with the `async` feature enabled.
+pub struct AsyncArduino {
+    arduino: Arduino,
+}
+
+impl AsyncArduino {
+
+    /// Connects to the Arduino with the given board, which has to be running Firmata.
+    /// Waits at most `DEFAULT_HANDSHAKE_TIMEOUT` for the Arduino to complete the Firmata handshake.
+    ///
+    /// # Errors
+    /// * as described for `Arduino::connect`.
+    pub async fn connect(board: &Board) -> Result<AsyncArduino, Error> {
+        AsyncArduino::connect_with_timeout(board, DEFAULT_HANDSHAKE_TIMEOUT).await
+    }
+
+    /// Connects to the Arduino with the given board, as with `AsyncArduino::connect`, but waits
+    /// the given time for the Firmata handshake.
+    pub async fn connect_with_timeout(board: &Board, timeout: Duration) -> Result<AsyncArduino, Error> {
+        let port = tokio_serial::new(board.port(), BAUD_RATE)
+            .open_native_async()
+            .map_err(|_| Error::PortOpenFailure)?;
+
+        AsyncArduino::connect_stream(port, timeout).await
+    }
+
+    /// Connects to an Arduino running Firmata over the given async stream, waiting the given time
+    /// for the Firmata handshake.
+    ///
+    /// # Errors
+    /// * as described for `Arduino::connect_transport`.
+    pub async fn connect_stream<S>(stream: S, timeout: Duration) -> Result<AsyncArduino, Error>
+    where S: AsyncRead + AsyncWrite + Send + 'static {
+        let transport = AsyncStreamTransport::spawn(stream);
+
+        // The handshake waits for multiple responses, so it is run on a blocking thread.
+        let arduino = tokio::task::spawn_blocking(move || Arduino::connect_transport(transport, timeout))
+            .await
+            .map_err(|_| Error::Disconnected)??;
+
+        Ok(AsyncArduino { arduino })
+    }
+
+    /// The firmware running on this Arduino, as reported when connecting.
+    pub fn firmware(&self) -> &Firmware { self.arduino.firmware() }
+
+    /// A collection of the digital pins for this Arduino.
+    pub fn digital_pins(&self) -> &Vec<DigitalPin> { self.arduino.digital_pins() }
+
+    /// A collection of the analog pins for this Arduino, ordered by their channel.
+    pub fn analog_pins(&self) -> &Vec<AnalogPin> { self.arduino.analog_pins() }
+
+    /// Writes a value to a given pin, as with `Arduino::write`.
+    pub fn write(&mut self, pin_index: i32, value: i32) -> Result<(), Error> {
+        self.arduino.write(pin_index, value)
+    }
+
+    /// Sets the mode of a given pin, as with `Arduino::set_pin_mode`.
+    pub fn set_pin_mode(&mut self, pin_index: i32, mode: PinMode) -> Result<(), Error> {
+        self.arduino.set_pin_mode(pin_index, mode)
+    }
+
+    /// Reads the current value of a given digital pin, as with `Arduino::read_digital`.
+    /// Waits at most `DEFAULT_READ_TIMEOUT` for the value.
+    pub async fn read_digital(&mut self, pin_index: i32) -> Result<i32, Error> {
+        self.read_digital_with_timeout(pin_index, DEFAULT_READ_TIMEOUT).await
+    }
+
+    /// Reads the current value of a given digital pin, as with `AsyncArduino::read_digital`, but
+    /// waits the given time for the value.
+    pub async fn read_digital_with_timeout(&mut self, pin_index: i32, timeout: Duration) -> Result<i32, Error> {
+        self.arduino.check_digital_input(pin_index)?;
+
+        tokio::time::timeout(timeout, self.arduino.reader().digital_value_async(pin_index))
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    /// Reads the latest sample from a given analog channel, as with `Arduino::read_analog`.
+    /// Waits at most `DEFAULT_READ_TIMEOUT` for the sample.
+    pub async fn read_analog(&mut self, channel: u8) -> Result<AnalogPin, Error> {
+        self.read_analog_with_timeout(channel, DEFAULT_READ_TIMEOUT).await
+    }
+
+    /// Reads the latest sample from a given analog channel, as with `AsyncArduino::read_analog`,
+    /// but waits the given time for the sample.
+    pub async fn read_analog_with_timeout(&mut self, channel: u8, timeout: Duration) -> Result<AnalogPin, Error> {
+        self.arduino.enable_analog_reporting(channel)?;
+
+        let value = tokio::time::timeout(timeout, self.arduino.reader().analog_value_async(channel))
+            .await
+            .map_err(|_| Error::Timeout)??;
+        self.arduino.update_analog_value(channel, value)
+    }
+
+    /// Subscribes to the events produced by the given pins, as with `Arduino::subscribe`.
+    ///
+    /// The returned channel is closed when the connection to the Arduino is lost.
+    pub fn subscribe(&mut self, pins: &[Pin]) -> Result<async_mpsc::UnboundedReceiver<PinEvent>, Error> {
+        self.arduino.prepare_subscription(pins)?;
+
+        let (sender, receiver) = async_mpsc::unbounded_channel();
+        self.arduino.reader().subscribe_with(Some(pins.iter().cloned().collect()), move |event| {
+            sender.send(event).is_ok()
+        });
+
+        Ok(receiver)
+    }
+
+    /// Subscribes to the events produced by all pins, as with `Arduino::subscribe_all`.
+    pub fn subscribe_all(&self) -> async_mpsc::UnboundedReceiver<PinEvent> {
+        let (sender, receiver) = async_mpsc::unbounded_channel();
+        self.arduino.reader().subscribe_with(None, move |event| sender.send(event).is_ok());
+
+        receiver
+    }
+}
+
+/// A transport whose halves are bridged to an async stream by tasks on the current runtime.
+struct AsyncStreamTransport {
+    incoming: mpsc::Receiver<Vec<u8>>,
+    outgoing: async_mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl AsyncStreamTransport {
+
+    /// Spawns the tasks reading from and writing to the given stream.
+    /// The tasks stop when the transport's halves are dropped, or the stream fails.
+    fn spawn<S: AsyncRead + AsyncWrite + Send + 'static>(stream: S) -> AsyncStreamTransport {
+        let (mut source, mut sink) = tokio::io::split(stream);
+        let (incoming_sender, incoming) = mpsc::channel();
+        let (outgoing, mut outgoing_receiver) = async_mpsc::unbounded_channel::<Vec<u8>>();
+        let (stop_sender, mut stop_receiver) = oneshot::channel::<()>();
+
+        tokio::spawn(async move {
+            while let Some(bytes) = outgoing_receiver.recv().await {
+                if sink.write_all(&bytes).await.is_err() || sink.flush().await.is_err() { break; }
+            }
+
+            // Dropping the sender stops the reading task.
+            drop(stop_sender);
+        });
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 64];
+
+            loop {
+                let count = tokio::select! {
+                    result = source.read(&mut buffer) => match result {
+                        Ok(count) if count > 0 => count,
+                        _ => break,
+                    },
+                    _ = &mut stop_receiver => break,
+                };
+
+                if incoming_sender.send(buffer[..count].to_vec()).is_err() { break; }
+            }
+        });
+
+        AsyncStreamTransport { incoming, outgoing }
+    }
+}
+
+impl Transport for AsyncStreamTransport {
+
+    fn split(self) -> io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+        Ok((Box::new(ChannelReader::new(self.incoming)), Box::new(ChannelWriter(self.outgoing))))
+    }
+}
+
+/// The writing half of an `AsyncStreamTransport`, which queues bytes for the writing task.
+struct ChannelWriter(async_mpsc::UnboundedSender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.0.send(buffer.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::arduino::sim::{VirtualBoard, Layout, Input};
+
+    /// Connects to a given virtual board over an in-memory async stream.
+    async fn connect(board: &VirtualBoard) -> AsyncArduino {
+        connect_mutable(board, Arc::new(AtomicBool::new(false))).await
+    }
+
+    /// Connects to a given virtual board over an in-memory async stream, dropping everything the
+    /// board sends while it is muted.
+    async fn connect_mutable(board: &VirtualBoard, muted: Arc<AtomicBool>) -> AsyncArduino {
+        let (host, device) = tokio::io::duplex(1024);
+        let (mut device_source, mut device_sink) = tokio::io::split(device);
+        let (mut board_source, mut board_sink) = board.transport().split().unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 64];
+
+            while let Ok(count) = device_source.read(&mut buffer).await {
+                if count == 0 || board_sink.write_all(&buffer[..count]).is_err() { break; }
+            }
+        });
+
+        let (sender, mut receiver) = async_mpsc::unbounded_channel::<Vec<u8>>();
+
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+
+            loop {
+                match board_source.read(&mut buffer) {
+                    Ok(_) if muted.load(Ordering::SeqCst) => continue,
+                    Ok(count) => if sender.send(buffer[..count].to_vec()).is_err() { break; },
+                    Err(error) if error.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(_) => break,
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            while let Some(bytes) = receiver.recv().await {
+                if device_sink.write_all(&bytes).await.is_err() { break; }
+            }
+        });
+
+        AsyncArduino::connect_stream(host, Duration::from_secs(1)).await.unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write() {
+        let board = VirtualBoard::new(Layout::uno());
+        let mut arduino = connect(&board).await;
+
+        arduino.set_pin_mode(13, PinMode::DigitalOutput).unwrap();
+        arduino.write(13, 1).unwrap();
+
+        // Writes are queued, so they may take a moment to arrive.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(board.pin_value(13), Some(1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reads() {
+        let board = VirtualBoard::new(Layout::uno());
+        let mut arduino = connect(&board).await;
+
+        board.apply(Input::Digital { pin: 4, value: true });
+        board.apply(Input::Analog { channel: 1, value: 300 });
+        arduino.set_pin_mode(4, PinMode::DigitalInput).unwrap();
+
+        assert_eq!(arduino.read_digital(4).await, Ok(1));
+        assert_eq!(arduino.read_analog(1).await.unwrap().value(), 300);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_timeouts() {
+        let board = VirtualBoard::new(Layout::uno());
+        let muted = Arc::new(AtomicBool::new(false));
+        let mut arduino = connect_mutable(&board, Arc::clone(&muted)).await;
+
+        muted.store(true, Ordering::SeqCst);
+        arduino.set_pin_mode(4, PinMode::DigitalInput).unwrap();
+
+        let timeout = Duration::from_millis(50);
+
+        assert_eq!(arduino.read_digital_with_timeout(4, timeout).await, Err(Error::Timeout));
+        assert_eq!(arduino.read_analog_with_timeout(1, timeout).await.unwrap_err(), Error::Timeout);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn events() {
+        let board = VirtualBoard::new(Layout::uno());
+        let mut arduino = connect(&board).await;
+
+        let mut events = arduino.subscribe(&[Pin::Analog(0)]).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        board.apply(Input::Analog { channel: 0, value: 10 });
+
+        let values = [events.recv().await.unwrap().value(), events.recv().await.unwrap().value()];
+
+        assert_eq!(values, [0, 10]);
+    }
+}
@@ -1,77 +1,357 @@
 use std::sync::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::time::{Duration, Instant};
+use std::convert::TryFrom;
 
 use crate::Board;
+use crate::cli;
 use crate::arduino::DigitalPin;
+use crate::arduino::AnalogPin;
 use crate::arduino::PinMode;
+use crate::arduino::{Pin, PinEvent};
+use crate::arduino::Servo;
+use crate::arduino::I2cReply;
+use crate::arduino::Firmware;
+use crate::arduino::{Transport, SerialTransport};
+use crate::arduino::event::{Reader, PINS_PER_PORT};
+use crate::arduino::servo::ServoConfig;
+use crate::arduino::FirmataPin;
+use crate::arduino::firmata::{Message, I2cMode};
 
+/// Analog messages can only address pins up to this index.
+const MAX_ANALOG_MESSAGE_PIN: i32 = 15;
+
+/// The largest value which can be encoded in two 7-bit data bytes.
+const MAX_14_BIT_VALUE: u16 = 0x3FFF;
+
+/// The largest 7-bit I2C address.
+const MAX_I2C_ADDRESS: u8 = 0x7F;
+
+/// StandardFirmata replies with this register, when reading without specifying a register.
+const UNSPECIFIED_I2C_REGISTER: u16 = 0xFF;
+
+/// The time waited for an I2C reply, unless configured otherwise.
+const DEFAULT_I2C_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The time waited for the Arduino's pin states, when synchronizing them via
+/// `Arduino::sync_pin_states`.
+const PIN_STATE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The interval at which handshake queries are repeated, as an Arduino ignores them while it is
+/// still starting up.
+const HANDSHAKE_QUERY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The time waited for an Arduino to report a value, when reading via `Arduino::read_analog` or
+/// `Arduino::read_digital`.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The time waited for an Arduino to complete the Firmata handshake, when connecting via
+/// `Arduino::connect`.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The kinds of errors that can occur as a result of communicating with an Arduino.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Error {
     InvalidPinIndex,
     ValueOutOfBounds,
     InvalidMode,
     Unimplemented,
+    Disconnected,
+    Timeout,
+    PortOpenFailure,
+    HandshakeTimeout,
+    /// An Arduino sent data during the handshake, but none of it was a Firmata message. This
+    /// occurs if the Arduino is running a sketch other than Firmata.
+    NotFirmata,
+    /// Firmata could not be flashed onto an Arduino, as attempted by `Arduino::connect_or_flash`.
+    FlashFailure,
+    /// An Arduino reported a pin mode with the given raw value, which is not a known `PinMode`.
+    UnknownPinMode(u8),
+    /// An I2C device replied with a different number of bytes than were requested.
+    I2cReplyLength { expected: usize, actual: usize },
 }
 
 /// A handle on an Arduino, for communicating with it via the Firmata protocol.
 pub struct Arduino {
-    board: firmata::Board,
+    firmware: Firmware,
+    firmata_pins: Vec<FirmataPin>,
     digital_pins: Vec<DigitalPin>,
+    analog_pins: Vec<AnalogPin>,
+    reporting_channels: HashSet<u8>,
+    reporting_ports: HashSet<u8>,
+    servos: HashMap<i32, ServoConfig>,
+    is_i2c_enabled: bool,
+    i2c_timeout: Duration,
+    writer: Box<dyn Write + Send>,
+    reader: Reader,
 }
 
 impl Arduino {
 
     /// Creates an Arduino bound to a given board.
+    ///
+    /// # Panics
+    /// * if connecting to the Arduino fails, as described for `Arduino::connect`.
     pub fn from(board: &Board) -> Arduino {
-        let board = firmata::Board::new(board.port());
-        let digital_pins = Arduino::digital_pins_for_board(&board);
+        Arduino::connect(board).expect("Connecting to the Arduino failed.")
+    }
+
+    /// Connects to the Arduino with the given board, which has to be running Firmata.
+    /// Waits at most `DEFAULT_HANDSHAKE_TIMEOUT` for the Arduino to complete the Firmata handshake.
+    ///
+    /// Messages sent by the Arduino are read on a background thread, which stops when the
+    /// Arduino instance is dropped.
+    ///
+    /// # Errors
+    /// * `PortOpenFailure`, if the board's port can not be opened or configured.
+    /// * `HandshakeTimeout`, if the Arduino does not complete the handshake in time. This occurs
+    ///   if the Arduino is not running Firmata and does not send any data.
+    /// * `NotFirmata`, if the Arduino only sends data which is not Firmata messages.
+    /// * `UnknownPinMode`, if the Arduino reports a pin mode which is not known.
+    pub fn connect(board: &Board) -> Result<Arduino, Error> {
+        Arduino::connect_with_timeout(board, DEFAULT_HANDSHAKE_TIMEOUT)
+    }
+
+    /// Connects to the Arduino with the given board, as with `Arduino::connect`, but waits the
+    /// given time for the Firmata handshake.
+    pub fn connect_with_timeout(board: &Board, timeout: Duration) -> Result<Arduino, Error> {
+        let transport = SerialTransport::open(board.port()).map_err(|_| Error::PortOpenFailure)?;
+        Arduino::connect_transport(transport, timeout)
+    }
 
-        Arduino { board, digital_pins }
+    /// Connects to the Arduino with the given board, as with `Arduino::connect`, but flashes
+    /// StandardFirmata onto it first if necessary.
+    ///
+    /// Flashing happens if the Arduino does not run Firmata, or if its firmware is outdated (see
+    /// `Firmware::is_outdated`). It is done via `cli::flash_firmata`, which also installs the
+    /// board's core and the Firmata library if they are missing. Afterwards the connection is
+    /// retried once.
+    ///
+    /// # Errors
+    /// * `FlashFailure`, if flashing is necessary but fails.
+    /// * as described for `Arduino::connect`.
+    pub fn connect_or_flash(board: &Board) -> Result<Arduino, Error> {
+        Arduino::connect_or_flash_with(
+            || Arduino::connect(board),
+            || cli::flash_firmata(board).map_err(|_| Error::FlashFailure),
+        )
     }
 
-    /// Converts the `firmata::Board`'s collection of `firmata::Pin`s to a collection of
-    /// `arduino::Pin`s.
-    fn digital_pins_for_board(board: &firmata::Board) -> Vec<DigitalPin> {
-        let (initial_tx, mut rx) = mpsc::channel::<Vec<DigitalPin>>();
+    /// Connects via the given function, flashing Firmata via the other function and reconnecting
+    /// if the connected Arduino does not run up to date Firmata.
+    fn connect_or_flash_with<C, F>(connect: C, flash: F) -> Result<Arduino, Error>
+    where C: Fn() -> Result<Arduino, Error>, F: FnOnce() -> Result<(), Error> {
+        match connect() {
+            Ok(arduino) if !arduino.firmware.is_outdated() => return Ok(arduino),
+            // The connection has to be closed, so that the port can be used for flashing.
+            Ok(arduino) => drop(arduino),
+            Err(Error::HandshakeTimeout) | Err(Error::NotFirmata) => {},
+            Err(error) => return Err(error),
+        }
+
+        flash()?;
+        connect()
+    }
 
-        initial_tx.send(vec![])
-            .expect("Sending to MPSC channel failed.");
+    /// Connects to an Arduino running Firmata over the given transport, waiting the given time for
+    /// the Firmata handshake.
+    ///
+    /// Messages sent by the Arduino are read on a background thread, which stops when the
+    /// Arduino instance is dropped.
+    ///
+    /// # Errors
+    /// * `PortOpenFailure`, if the transport can not be split.
+    /// * `HandshakeTimeout`, if the Arduino does not complete the handshake in time. The handshake
+    ///   includes synchronizing the Arduino's pin states.
+    /// * `NotFirmata`, if the Arduino only sends data which is not Firmata messages.
+    /// * `Disconnected`, if the transport fails during the handshake.
+    /// * `UnknownPinMode`, if the Arduino reports a pin mode which is not known.
+    pub fn connect_transport<T: Transport>(transport: T, timeout: Duration) -> Result<Arduino, Error> {
+        let deadline = Instant::now() + timeout;
+        let (source, mut writer) = transport.split().map_err(|_| Error::PortOpenFailure)?;
+        let reader = Reader::spawn(source);
+
+        let (firmware, firmata_pins) = match Arduino::handshake(&reader, &mut writer, deadline) {
+            Err(Error::HandshakeTimeout) if reader.has_only_received_garbage() => Err(Error::NotFirmata),
+            result => result,
+        }?;
+        let digital_pins = Arduino::digital_pins_for_board(&firmata_pins)?;
+        let analog_pins = Arduino::analog_pins_for_board(&firmata_pins)?;
+
+        let mut arduino = Arduino {
+            firmware, firmata_pins, digital_pins, analog_pins,
+            reporting_channels: HashSet::new(),
+            reporting_ports: HashSet::new(),
+            servos: HashMap::new(),
+            is_i2c_enabled: false,
+            i2c_timeout: DEFAULT_I2C_TIMEOUT,
+            writer,
+            reader,
+        };
+
+        match arduino.sync_pin_states_until(deadline) {
+            Err(Error::Timeout) => Err(Error::HandshakeTimeout),
+            result => result.map(|_| arduino),
+        }
+    }
 
-        for firmata_pin in board.pins.iter().filter(|pin| !pin.analog ) {
-            let current_rx = rx;
-            let (current_tx, next_rx) = mpsc::channel();
-            rx = next_rx;
+    /// Queries an Arduino's firmware, capabilities and analog mapping, and converts the latter to a
+    /// collection of `FirmataPin`s.
+    ///
+    /// # Errors
+    /// * `HandshakeTimeout`, if the Arduino does not respond before the deadline.
+    /// * `Disconnected`, if the connection to the Arduino is lost.
+    fn handshake(
+        reader: &Reader, writer: &mut Box<dyn Write + Send>, deadline: Instant
+    ) -> Result<(Firmware, Vec<FirmataPin>), Error> {
+        let protocol_version = Arduino::query(writer, &Message::ProtocolVersionQuery, deadline, |timeout| {
+            reader.protocol_version(timeout)
+        })?;
+        let (major, minor, name) = Arduino::query(writer, &Message::FirmwareQuery, deadline, |timeout| {
+            reader.firmware(timeout)
+        })?;
+        let firmware = Firmware { name, version: (major, minor), protocol_version };
 
-            crossbeam::thread::scope(|scope| {
-                scope.spawn(move |_| {
-                    let digital_pin = DigitalPin::from_digital(firmata_pin);
-                    let mut pin_list = current_rx.recv()
-                        .expect("MPSC channel chain failed.");
+        let capabilities = Arduino::query(writer, &Message::CapabilityQuery, deadline, |timeout| {
+            reader.capabilities(timeout)
+        })?;
+        let analog_mapping = Arduino::query(writer, &Message::AnalogMappingQuery, deadline, |timeout| {
+            reader.analog_mapping(timeout)
+        })?;
 
-                    pin_list.push(digital_pin);
-                    current_tx.send(pin_list)
-                        .expect("Sending to MPSC channel failed.");
-                });
+        let firmata_pins = capabilities.into_iter()
+            .enumerate()
+            .map(|(pin_index, modes)| FirmataPin {
+                modes,
+                analog_channel: analog_mapping.get(pin_index).cloned().flatten(),
+                value: 0,
+                mode: 0,
             })
-            .expect("Crossbeam scope failed.");
+            .collect();
+
+        Ok((firmware, firmata_pins))
+    }
+
+    /// Sends a given query repeatedly, until the given function produces its response or the
+    /// deadline passes.
+    fn query<T, F: Fn(Duration) -> Result<T, Error>>(
+        writer: &mut Box<dyn Write + Send>, query: &Message, deadline: Instant, response: F
+    ) -> Result<T, Error> {
+        loop {
+            let now = Instant::now();
+            if now >= deadline { return Err(Error::HandshakeTimeout); }
+
+            writer.write_all(&query.encode()).map_err(|_| Error::Disconnected)?;
+
+            match response(HANDSHAKE_QUERY_INTERVAL.min(deadline - now)) {
+                Err(Error::Timeout) => continue,
+                result => return result,
+            }
         }
+    }
 
-        rx.recv()
-            .expect("MPSC channel chain failed.")
+    /// Converts a collection of `FirmataPin`s to a collection of `arduino::DigitalPin`s.
+    ///
+    /// # Errors
+    /// * `UnknownPinMode`, if one of the pins has an unknown mode.
+    fn digital_pins_for_board(firmata_pins: &[FirmataPin]) -> Result<Vec<DigitalPin>, Error> {
+        firmata_pins.iter()
+            .filter(|pin| pin.analog_channel.is_none())
+            .map(DigitalPin::from_digital)
+            .collect()
     }
 
+    /// Converts the analog pins in a collection of `FirmataPin`s to a collection of
+    /// `arduino::AnalogPin`s, ordered by their channel.
+    ///
+    /// # Errors
+    /// * `UnknownPinMode`, if one of the pins does not support `AnalogInput` mode.
+    fn analog_pins_for_board(firmata_pins: &[FirmataPin]) -> Result<Vec<AnalogPin>, Error> {
+        let mut analog_pins = firmata_pins.iter()
+            .enumerate()
+            .filter_map(|(pin_index, pin)| Some((pin.analog_channel?, pin_index, pin)))
+            .map(|(channel, pin_index, pin)| AnalogPin::from_analog(channel, pin_index, pin))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        analog_pins.sort_by_key(AnalogPin::channel);
+        Ok(analog_pins)
+    }
+
+    /// The firmware running on this Arduino, as reported when connecting.
+    pub fn firmware(&self) -> &Firmware { &self.firmware }
+
     /// A collection of the digital pins for this Arduino.
     pub fn digital_pins(&self) -> &Vec<DigitalPin> { &self.digital_pins }
 
+    /// A collection of the analog pins for this Arduino, ordered by their channel.
+    pub fn analog_pins(&self) -> &Vec<AnalogPin> { &self.analog_pins }
+
+    /// Reads the latest sample from a given analog channel.
+    /// If the channel is read for the first time, its pin is set to analog input mode and analog
+    /// reporting is enabled for it.
+    ///
+    /// This call blocks until the Arduino has reported a sample for the channel, waiting at most
+    /// `DEFAULT_READ_TIMEOUT`.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have the given analog channel.
+    /// * `Timeout`, if the Arduino did not report a sample in time. This occurs if its firmware
+    ///   does not report analog values, e.g. because it is not StandardFirmata.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn read_analog(&mut self, channel: u8) -> Result<AnalogPin, Error> {
+        self.read_analog_with_timeout(channel, DEFAULT_READ_TIMEOUT)
+    }
+
+    /// Reads the latest sample from a given analog channel, as with `Arduino::read_analog`, but
+    /// waits the given time for the sample.
+    pub fn read_analog_with_timeout(&mut self, channel: u8, timeout: Duration) -> Result<AnalogPin, Error> {
+        self.enable_analog_reporting(channel)?;
+
+        let value = self.reader.analog_value(channel, timeout)?;
+        self.update_analog_value(channel, value)
+    }
+
+    /// Records the latest sample read from a given analog channel, returning the channel's pin.
+    pub(crate) fn update_analog_value(&mut self, channel: u8, value: i32) -> Result<AnalogPin, Error> {
+        let pin = self.analog_pins.iter_mut()
+            .find(|pin| pin.channel == channel)
+            .ok_or(Error::InvalidPinIndex)?;
+        pin.value = value;
+
+        Ok(pin.clone())
+    }
+
+    /// Sets the pin of a given analog channel to analog input mode and enables analog reporting
+    /// for it, if this has not happened before.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have the given analog channel.
+    pub(crate) fn enable_analog_reporting(&mut self, channel: u8) -> Result<(), Error> {
+        let pin = self.analog_pins.iter()
+            .find(|pin| pin.channel == channel)
+            .ok_or(Error::InvalidPinIndex)?;
+
+        if self.reporting_channels.insert(channel) {
+            let pin_index = pin.pin_index;
+
+            self.firmata_set_pin_mode(pin_index, PinMode::AnalogInput)?;
+            self.send(Message::ReportAnalog { channel, enabled: true })?;
+        }
+
+        Ok(())
+    }
+
     pub fn write(&mut self, pin_index: i32, value: i32) -> Result<(), Error> {
         if let Some(pin) = self.digital_pins.get(pin_index as usize) {
             if pin.valid_values().contains(&value) {
                 match pin.mode() {
-                    PinMode::DigitalOutput => self.board.digital_write(pin_index, value),
-                    PinMode::Pwm => self.board.analog_write(pin_index, value),
+                    PinMode::DigitalOutput => self.digital_write(pin_index, value)?,
+                    PinMode::Pwm | PinMode::Servo => self.analog_write(pin_index, value)?,
                     _ => return Err(Error::Unimplemented),
                 }
 
-                self.digital_pins= Arduino::digital_pins_for_board(&self.board);
+                self.digital_pins[pin_index as usize].value = value;
                 Ok(())
             } else {
                 Err(Error::ValueOutOfBounds)
@@ -84,10 +364,12 @@ impl Arduino {
     pub fn set_pin_mode(&mut self, pin_index: i32, mode: PinMode) -> Result<(), Error> {
         if let Some(pin) = self.digital_pins.get(pin_index as usize) {
             if pin.valid_modes.contains(&mode) {
-                self.board.set_pin_mode(pin_index, mode as u8);
+                self.firmata_set_pin_mode(pin_index as usize, mode)?;
 
-                self.digital_pins= Arduino::digital_pins_for_board(&self.board);
-                Ok(())
+                if mode != PinMode::Servo { self.servos.remove(&pin_index); }
+
+                self.refresh_digital_pin(pin_index as usize)?;
+                self.update_port_reporting((pin_index as usize / PINS_PER_PORT) as u8)
             } else {
                 Err(Error::InvalidMode)
             }
@@ -95,4 +377,442 @@ impl Arduino {
             Err(Error::InvalidPinIndex)
         }
     }
+
+    /// Queries the mode and value of all pins from the Arduino, and updates the pins returned by
+    /// `digital_pins` accordingly. This is only necessary if pins were changed by other means than
+    /// this instance, as it keeps track of all changes it makes.
+    ///
+    /// Pins reported in a mode which is not a known `PinMode` keep their previous state.
+    ///
+    /// # Errors
+    /// * `Timeout`, if the Arduino does not report all pin states within a second.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn sync_pin_states(&mut self) -> Result<(), Error> {
+        self.sync_pin_states_until(Instant::now() + PIN_STATE_TIMEOUT)
+    }
+
+    /// Queries the mode and value of all pins from the Arduino, waiting for the responses until a
+    /// given deadline. Port reporting is updated for the new modes.
+    fn sync_pin_states_until(&mut self, deadline: Instant) -> Result<(), Error> {
+        let response_count = self.reader.pin_state_count();
+
+        for pin_index in 0..self.firmata_pins.len() {
+            self.send(Message::PinStateQuery { pin: pin_index as u8 })?;
+        }
+
+        for pin_index in 0..self.firmata_pins.len() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let (mode, state) = self.reader.pin_state(pin_index as u8, response_count, timeout)?;
+
+            if PinMode::try_from(mode).is_err() { continue; }
+
+            let pin = &mut self.firmata_pins[pin_index];
+            pin.mode = mode;
+            pin.value = state as i32;
+
+            if pin.analog_channel.is_none() { self.refresh_digital_pin(pin_index)?; }
+        }
+
+        let port_count = self.digital_pins.len().div_ceil(PINS_PER_PORT);
+        (0..port_count).try_for_each(|port| self.update_port_reporting(port as u8))
+    }
+
+    /// Updates the digital pin of the Firmata pin at a given index, and records whether it produces
+    /// events.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Firmata pin at the given index is not a digital pin.
+    fn refresh_digital_pin(&mut self, pin_index: usize) -> Result<(), Error> {
+        let digital_index = self.digital_index(pin_index).ok_or(Error::InvalidPinIndex)?;
+        let pin = DigitalPin::from_digital(&self.firmata_pins[pin_index])?;
+        self.reader.set_input(pin_index as i32, Arduino::is_reporting_mode(pin.mode()));
+
+        let digital_pin = self.digital_pins.get_mut(digital_index).ok_or(Error::InvalidPinIndex)?;
+        *digital_pin = pin;
+
+        Ok(())
+    }
+
+    /// The position in `digital_pins` of the Firmata pin at a given index, i.e. its position among
+    /// the non-analog pins, or `None` if it is an analog pin or does not exist.
+    fn digital_index(&self, pin_index: usize) -> Option<usize> {
+        let is_digital = |pin: &FirmataPin| pin.analog_channel.is_none();
+
+        if !is_digital(self.firmata_pins.get(pin_index)?) { return None; }
+
+        Some(self.firmata_pins[..pin_index].iter().filter(|pin| is_digital(pin)).count())
+    }
+
+    /// Reads the current value of a given digital pin.
+    /// The pin has to be in `DigitalInput` or `InputPullup` mode.
+    ///
+    /// This call blocks until the Arduino has reported the value of the pin's port, waiting at
+    /// most `DEFAULT_READ_TIMEOUT`.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have a digital pin at the given index.
+    /// * `InvalidMode`, if the pin is not in an input mode.
+    /// * `Timeout`, if the Arduino did not report the value in time. This occurs if its firmware
+    ///   does not report digital values, e.g. because it is not StandardFirmata.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn read_digital(&mut self, pin_index: i32) -> Result<i32, Error> {
+        self.read_digital_with_timeout(pin_index, DEFAULT_READ_TIMEOUT)
+    }
+
+    /// Reads the current value of a given digital pin, as with `Arduino::read_digital`, but waits
+    /// the given time for the value.
+    pub fn read_digital_with_timeout(&mut self, pin_index: i32, timeout: Duration) -> Result<i32, Error> {
+        self.check_digital_input(pin_index)?;
+        self.reader.digital_value(pin_index, timeout)
+    }
+
+    /// Checks that a given digital pin exists and is in an input mode.
+    pub(crate) fn check_digital_input(&self, pin_index: i32) -> Result<(), Error> {
+        let pin = self.digital_pins.get(pin_index as usize).ok_or(Error::InvalidPinIndex)?;
+
+        if Arduino::is_reporting_mode(pin.mode()) { Ok(()) } else { Err(Error::InvalidMode) }
+    }
+
+    /// Subscribes to the events produced by the given pins.
+    /// Events are only produced for digital pins in an input mode. Analog reporting is enabled for
+    /// the given analog pins, as with `read_analog`.
+    ///
+    /// The returned channel is closed when the connection to the Arduino is lost.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have one of the given pins.
+    pub fn subscribe(&mut self, pins: &[Pin]) -> Result<mpsc::Receiver<PinEvent>, Error> {
+        self.prepare_subscription(pins)?;
+        Ok(self.reader.subscribe(Some(pins.iter().cloned().collect())))
+    }
+
+    /// Checks that the given pins exist and enables analog reporting for the analog pins among
+    /// them.
+    pub(crate) fn prepare_subscription(&mut self, pins: &[Pin]) -> Result<(), Error> {
+        for &pin in pins {
+            match pin {
+                Pin::Digital(pin_index) => {
+                    if pin_index < 0 || pin_index as usize >= self.digital_pins.len() {
+                        return Err(Error::InvalidPinIndex);
+                    }
+                },
+                Pin::Analog(channel) => self.enable_analog_reporting(channel)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The reader of the messages sent by the Arduino.
+    #[cfg(feature = "async")]
+    pub(crate) fn reader(&self) -> &Reader { &self.reader }
+
+    /// Subscribes to the events produced by all pins.
+    /// Analog pins only produce events once reporting has been enabled for them via `read_analog`
+    /// or `subscribe`.
+    ///
+    /// The returned channel is closed when the connection to the Arduino is lost.
+    pub fn subscribe_all(&self) -> mpsc::Receiver<PinEvent> {
+        self.reader.subscribe(None)
+    }
+
+    /// Attaches a servo to a given pin, which is set to `Servo` mode in the process.
+    /// The servo's pulse width is bounded by the given minimum and maximum (in microseconds).
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have a digital pin at the given index.
+    /// * `InvalidMode`, if the pin does not support `Servo` mode.
+    /// * `ValueOutOfBounds`, if the minimum pulse width is not less than the maximum, or the
+    ///   maximum exceeds 16383.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn attach_servo(
+        &mut self, pin_index: i32, min_pulse_us: u16, max_pulse_us: u16
+    ) -> Result<Servo<'_>, Error> {
+        let pin = self.digital_pins.get(pin_index as usize).ok_or(Error::InvalidPinIndex)?;
+
+        if !pin.valid_modes.contains(&PinMode::Servo) { return Err(Error::InvalidMode); }
+        if min_pulse_us >= max_pulse_us || max_pulse_us > MAX_14_BIT_VALUE {
+            return Err(Error::ValueOutOfBounds);
+        }
+
+        self.send(Message::ServoConfig {
+            pin: pin_index as u8, min_pulse: min_pulse_us, max_pulse: max_pulse_us,
+        })?;
+        self.set_pin_mode(pin_index, PinMode::Servo)?;
+
+        let config = ServoConfig { min_pulse: min_pulse_us, max_pulse: max_pulse_us };
+        self.servos.insert(pin_index, config);
+
+        Ok(Servo::new(self, pin_index, config))
+    }
+
+    /// A handle on the servo attached to a given pin.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have a digital pin at the given index.
+    /// * `InvalidMode`, if no servo is attached to the pin via `attach_servo`.
+    pub fn servo(&mut self, pin_index: i32) -> Result<Servo<'_>, Error> {
+        if self.digital_pins.get(pin_index as usize).is_none() { return Err(Error::InvalidPinIndex); }
+
+        let config = *self.servos.get(&pin_index).ok_or(Error::InvalidMode)?;
+
+        Ok(Servo::new(self, pin_index, config))
+    }
+
+    /// Enables I2C on the Arduino, with a given delay (in microseconds) between writing a register
+    /// and reading data from it. Some devices require such a delay.
+    ///
+    /// Calling this is optional, as I2C is enabled without a delay when it is first used.
+    ///
+    /// # Errors
+    /// * `ValueOutOfBounds`, if the delay exceeds 16383 microseconds.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn i2c_config(&mut self, delay_us: u16) -> Result<(), Error> {
+        if delay_us > MAX_14_BIT_VALUE { return Err(Error::ValueOutOfBounds); }
+
+        self.send(Message::I2cConfig { delay: delay_us })?;
+        self.is_i2c_enabled = true;
+
+        Ok(())
+    }
+
+    /// Sets the time that `i2c_read` waits for a reply. The default is one second.
+    pub fn set_i2c_timeout(&mut self, timeout: Duration) {
+        self.i2c_timeout = timeout;
+    }
+
+    /// Writes the given bytes to the I2C device with a given 7-bit address.
+    ///
+    /// # Errors
+    /// * `ValueOutOfBounds`, if the address exceeds 7 bits.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn i2c_write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        let values: Vec<u16> = bytes.iter().map(|&byte| byte.into()).collect();
+
+        self.i2c_request(address, I2cMode::Write, &values)
+    }
+
+    /// Reads a given number of bytes from a register of the I2C device with a given 7-bit address.
+    /// This call blocks until the Arduino replies or the I2C timeout elapses.
+    ///
+    /// # Errors
+    /// * `ValueOutOfBounds`, if the address exceeds 7 bits or the length exceeds 16383 bytes.
+    /// * `Timeout`, if the Arduino did not reply in time.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn i2c_read(&mut self, address: u8, register: u8, len: u16) -> Result<I2cReply, Error> {
+        self.i2c_read_from(address, Some(register), len)
+    }
+
+    /// Reads a given number of bytes from the I2C device with a given 7-bit address, optionally
+    /// writing a register beforehand.
+    pub(crate) fn i2c_read_from(
+        &mut self, address: u8, register: Option<u8>, len: u16
+    ) -> Result<I2cReply, Error> {
+        let reply_count = self.reader.i2c_reply_count();
+
+        let (values, reply_register) = match register {
+            Some(register) => (vec![register.into(), len], register.into()),
+            None => (vec![len], UNSPECIFIED_I2C_REGISTER),
+        };
+
+        self.i2c_request(address, I2cMode::Read, &values)?;
+        self.reader.i2c_reply(address.into(), reply_register, reply_count, self.i2c_timeout)
+    }
+
+    /// Continuously reads a given number of bytes from a register of the I2C device with a given
+    /// 7-bit address. Reading stops when `i2c_stop_reading` is called for the address.
+    ///
+    /// The replies of the device are sent over the returned channel, which is closed when the
+    /// connection to the Arduino is lost.
+    ///
+    /// # Errors
+    /// * `ValueOutOfBounds`, if the address exceeds 7 bits or the length exceeds 16383 bytes.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn i2c_subscribe(
+        &mut self, address: u8, register: u8, len: u16
+    ) -> Result<mpsc::Receiver<I2cReply>, Error> {
+        let replies = self.reader.subscribe_i2c(address.into());
+        self.i2c_request(address, I2cMode::ReadContinuously, &[register.into(), len])?;
+
+        Ok(replies)
+    }
+
+    /// Stops continuously reading from the I2C device with a given 7-bit address.
+    ///
+    /// # Errors
+    /// * `ValueOutOfBounds`, if the address exceeds 7 bits.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn i2c_stop_reading(&mut self, address: u8) -> Result<(), Error> {
+        self.i2c_request(address, I2cMode::StopReading, &[])
+    }
+
+    /// Sends an I2C request with the given values, enabling I2C beforehand if necessary.
+    fn i2c_request(&mut self, address: u8, mode: I2cMode, values: &[u16]) -> Result<(), Error> {
+        if address > MAX_I2C_ADDRESS || values.iter().any(|&value| value > MAX_14_BIT_VALUE) {
+            return Err(Error::ValueOutOfBounds);
+        }
+
+        if !self.is_i2c_enabled { self.i2c_config(0)?; }
+
+        self.send(Message::I2cRequest { address: address.into(), mode, data: values.to_vec() })
+    }
+
+    /// Writes a value to the servo attached to a given pin, and records it as the pin's value.
+    pub(crate) fn servo_write(&mut self, pin_index: i32, value: i32) -> Result<(), Error> {
+        self.analog_write(pin_index, value)?;
+
+        let pin = self.digital_pins.get_mut(pin_index as usize).ok_or(Error::InvalidPinIndex)?;
+        pin.value = value;
+
+        Ok(())
+    }
+
+    /// Writes an analog value to a given pin, using an extended analog message for pins which can
+    /// not be addressed by regular analog messages.
+    fn analog_write(&mut self, pin_index: i32, value: i32) -> Result<(), Error> {
+        self.firmata_pins[pin_index as usize].value = value;
+
+        if pin_index <= MAX_ANALOG_MESSAGE_PIN {
+            self.send(Message::Analog { pin: pin_index as u8, value: value as u16 })
+        } else {
+            self.send(Message::ExtendedAnalog { pin: pin_index as u8, value: value as u32 })
+        }
+    }
+
+    /// Writes a digital value to a given pin, by writing the values of all pins in its port.
+    fn digital_write(&mut self, pin_index: i32, value: i32) -> Result<(), Error> {
+        self.firmata_pins[pin_index as usize].value = value;
+
+        let port = pin_index as usize / PINS_PER_PORT;
+        let port_value = self.firmata_pins.iter()
+            .skip(port * PINS_PER_PORT)
+            .take(PINS_PER_PORT)
+            .enumerate()
+            .filter(|(_, pin)| pin.value != 0)
+            .fold(0u16, |port_value, (bit, _)| port_value | (1 << bit));
+
+        self.send(Message::DigitalPort { port: port as u8, value: port_value })
+    }
+
+    /// Sets the mode of the Firmata pin at a given index.
+    fn firmata_set_pin_mode(&mut self, pin_index: usize, mode: PinMode) -> Result<(), Error> {
+        self.firmata_pins[pin_index].mode = mode as u8;
+        self.send(Message::SetPinMode { pin: pin_index as u8, mode: mode as u8 })
+    }
+
+    /// Encodes a given message and writes it to the Arduino.
+    fn send(&mut self, message: Message) -> Result<(), Error> {
+        self.writer.write_all(&message.encode()).map_err(|_| Error::Disconnected)
+    }
+
+    /// Indicates whether pins in the given mode require their port to report digital values.
+    fn is_reporting_mode(mode: PinMode) -> bool {
+        mode == PinMode::DigitalInput || mode == PinMode::InputPullup
+    }
+
+    /// Enables or disables reporting for a given digital port, depending on whether any of its
+    /// pins are in an input mode.
+    fn update_port_reporting(&mut self, port: u8) -> Result<(), Error> {
+        let needs_reporting = self.digital_pins.iter()
+            .skip(port as usize * PINS_PER_PORT)
+            .take(PINS_PER_PORT)
+            .any(|pin| Arduino::is_reporting_mode(pin.mode()));
+
+        let is_reporting = self.reporting_ports.contains(&port);
+
+        // Enabling reporting for a port causes the Arduino to immediately report the port's value,
+        // so it is also re-enabled for already reporting ports to refresh their value.
+        if needs_reporting {
+            self.send(Message::ReportDigital { port, enabled: true })?;
+            self.reporting_ports.insert(port);
+        } else if is_reporting {
+            self.send(Message::ReportDigital { port, enabled: false })?;
+            self.reporting_ports.remove(&port);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    use crate::arduino::sim::{VirtualBoard, Layout};
+    use crate::arduino::firmata::ModeCapability;
+
+    fn connect(board: &VirtualBoard) -> Result<Arduino, Error> {
+        Arduino::connect_transport(board.transport(), Duration::from_secs(1))
+    }
+
+    #[test]
+    fn connect_without_flashing() {
+        let board = VirtualBoard::new(Layout::uno());
+        let flashed = Cell::new(false);
+
+        let result = Arduino::connect_or_flash_with(|| connect(&board), || {
+            flashed.set(true);
+            Ok(())
+        });
+
+        assert!(result.is_ok());
+        assert!(!flashed.get());
+    }
+
+    #[test]
+    fn flash_outdated_firmware() {
+        let board = VirtualBoard::new(Layout::uno());
+        board.set_firmware("StandardFirmata.ino", (2, 3), (2, 3));
+
+        let arduino = Arduino::connect_or_flash_with(|| connect(&board), || {
+            board.set_firmware("StandardFirmata.ino", (2, 5), (2, 5));
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(arduino.firmware().protocol_version(), (2, 5));
+    }
+
+    #[test]
+    fn flash_failure() {
+        let board = VirtualBoard::new(Layout::uno());
+        board.set_firmware("StandardFirmata.ino", (2, 3), (2, 3));
+
+        let result = Arduino::connect_or_flash_with(|| connect(&board), || Err(Error::FlashFailure));
+
+        assert_eq!(result.err(), Some(Error::FlashFailure));
+    }
+
+    #[test]
+    fn sync_interleaved_analog_pins() {
+        let capability = |mode: PinMode, resolution| ModeCapability { mode: mode as u8, resolution };
+        let digital = vec![capability(PinMode::DigitalInput, 1), capability(PinMode::DigitalOutput, 1)];
+        let analog = vec![capability(PinMode::AnalogInput, 10)];
+        let layout = Layout::new(
+            vec![digital.clone(), analog, digital],
+            vec![None, Some(0), None],
+        );
+
+        let board = VirtualBoard::new(layout);
+        let mut arduino = connect(&board).unwrap();
+
+        assert_eq!(arduino.digital_pins().len(), 2);
+        assert_eq!(arduino.digital_pins()[1].mode(), PinMode::DigitalOutput);
+
+        arduino.firmata_set_pin_mode(2, PinMode::DigitalInput).unwrap();
+        arduino.sync_pin_states().unwrap();
+
+        assert_eq!(arduino.digital_pins()[0].mode(), PinMode::DigitalOutput);
+        assert_eq!(arduino.digital_pins()[1].mode(), PinMode::DigitalInput);
+    }
+
+    #[test]
+    fn no_flashing_on_port_failure() {
+        let result = Arduino::connect_or_flash_with(
+            || Err(Error::PortOpenFailure),
+            || panic!("flashed despite a port failure"),
+        );
+
+        assert_eq!(result.err(), Some(Error::PortOpenFailure));
+    }
 }
@@ -0,0 +1,613 @@
+use std::io::{self, Read};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, Condvar, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::arduino::{Error, I2cReply};
+use crate::arduino::firmata::{Message, Parser, ModeCapability};
+
+/// The number of pins grouped into one Firmata digital port.
+pub(crate) const PINS_PER_PORT: usize = 8;
+
+/// The time the reader waits before polling its source again, if no bytes were available.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A pin on an Arduino, as referred to by pin events.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Pin {
+    /// A digital pin, identified by its index.
+    Digital(i32),
+    /// An analog pin, identified by its channel.
+    Analog(u8),
+}
+
+/// A value reported by an Arduino for one of its pins.
+///
+/// Digital pins produce an event whenever their value changes, while analog pins produce an event
+/// for every sample.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PinEvent {
+    pin: Pin,
+    value: i32,
+    timestamp: Instant,
+}
+
+impl PinEvent {
+
+    /// The pin whose value was reported.
+    pub fn pin(&self) -> Pin { self.pin }
+
+    /// The reported value.
+    pub fn value(&self) -> i32 { self.value }
+
+    /// The time at which the value was received.
+    pub fn timestamp(&self) -> Instant { self.timestamp }
+}
+
+/// A receiver of pin events, which may only be interested in certain pins.
+/// Events are passed to its send function, which indicates whether the receiver is still around.
+struct Subscriber {
+    pins: Option<HashSet<Pin>>,
+    send: Box<dyn Fn(PinEvent) -> bool + Send>,
+}
+
+/// A receiver of the replies of a given I2C device.
+struct I2cSubscriber {
+    address: u16,
+    sender: mpsc::Sender<I2cReply>,
+}
+
+/// The pin values, device replies and subscribers tracked by a reader.
+#[derive(Default)]
+struct State {
+    port_values: HashMap<u8, u16>,
+    analog_values: HashMap<u8, u16>,
+    input_pins: HashSet<i32>,
+    subscribers: Vec<Subscriber>,
+    i2c_reply_count: u64,
+    i2c_replies: HashMap<(u16, u16), (u64, I2cReply)>,
+    i2c_subscribers: Vec<I2cSubscriber>,
+    pin_state_count: u64,
+    pin_states: HashMap<u8, (u64, u8, u32)>,
+    protocol_version: Option<(u8, u8)>,
+    firmware: Option<(u8, u8, String)>,
+    capabilities: Option<Vec<Vec<ModeCapability>>>,
+    analog_mapping: Option<Vec<Option<u8>>>,
+    has_received_messages: bool,
+    has_received_garbage: bool,
+    is_stopped: bool,
+    is_disconnected: bool,
+}
+
+impl State {
+
+    /// Updates the tracked pin values or device replies according to a given message, and notifies
+    /// all interested subscribers.
+    fn apply(&mut self, message: Message, timestamp: Instant) {
+        let mut events = vec![];
+
+        match message {
+            Message::DigitalPort { port, value } => {
+                let previous = self.port_values.insert(port, value);
+
+                for bit in 0..PINS_PER_PORT {
+                    let pin = (usize::from(port) * PINS_PER_PORT + bit) as i32;
+                    let bit_value = (value >> bit) & 1;
+                    let has_changed = previous.is_none_or(|previous| (previous >> bit) & 1 != bit_value);
+
+                    if has_changed && self.input_pins.contains(&pin) {
+                        events.push(PinEvent { pin: Pin::Digital(pin), value: bit_value.into(), timestamp });
+                    }
+                }
+            },
+            Message::Analog { pin: channel, value } => {
+                self.analog_values.insert(channel, value);
+                events.push(PinEvent { pin: Pin::Analog(channel), value: value.into(), timestamp });
+            },
+            Message::I2cReply(reply) => {
+                // Subscribers whose receiving end was dropped are removed.
+                self.i2c_subscribers.retain(|subscriber| {
+                    subscriber.address != reply.address || subscriber.sender.send(reply.clone()).is_ok()
+                });
+
+                self.i2c_reply_count += 1;
+                self.i2c_replies.insert((reply.address, reply.register), (self.i2c_reply_count, reply));
+            },
+            Message::ProtocolVersion { major, minor } => self.protocol_version = Some((major, minor)),
+            Message::Firmware { major, minor, name } => self.firmware = Some((major, minor, name)),
+            Message::Capabilities(capabilities) => self.capabilities = Some(capabilities),
+            Message::AnalogMapping(mapping) => self.analog_mapping = Some(mapping),
+            Message::PinState { pin, mode, state } => {
+                self.pin_state_count += 1;
+                self.pin_states.insert(pin, (self.pin_state_count, mode, state));
+            },
+            _ => {},
+        }
+
+        // Subscribers whose receiving end was dropped are removed.
+        self.subscribers.retain(|subscriber| {
+            events.iter()
+                .filter(|event| subscriber.pins.as_ref().is_none_or(|pins| pins.contains(&event.pin)))
+                .all(|&event| (subscriber.send)(event))
+        });
+    }
+}
+
+/// The state shared between a reader and its background thread.
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    updated: Condvar,
+    #[cfg(feature = "async")]
+    updated_async: tokio::sync::Notify,
+}
+
+impl Shared {
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Reader state lock failed.")
+    }
+
+    /// Wakes up everyone waiting for the state to change.
+    fn notify_all(&self) {
+        self.updated.notify_all();
+
+        #[cfg(feature = "async")]
+        self.updated_async.notify_waiters();
+    }
+}
+
+/// A handle on a background thread, which parses the messages sent by an Arduino and tracks the
+/// values of its pins.
+///
+/// When the handle is dropped, the thread stops after its next read returns.
+pub(crate) struct Reader {
+    shared: Arc<Shared>,
+}
+
+impl Reader {
+
+    /// Spawns a background thread reading from the given source.
+    pub(crate) fn spawn<R: Read + Send + 'static>(source: R) -> Reader {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = Arc::clone(&shared);
+        thread::spawn(move || Reader::run(source, &thread_shared));
+
+        Reader { shared }
+    }
+
+    /// Reads and parses bytes from the source until the reader is stopped or the source fails.
+    fn run<R: Read>(mut source: R, shared: &Shared) {
+        let mut parser = Parser::default();
+        let mut buffer = [0u8; 64];
+        let mut has_received_messages = false;
+
+        while !shared.lock().is_stopped {
+            match source.read(&mut buffer) {
+                // Reading zero bytes means that the source was closed (e.g. by a TCP peer).
+                Ok(0) => break,
+                Ok(count) => {
+                    let timestamp = Instant::now();
+                    let messages: Vec<_> = buffer[..count].iter()
+                        .filter_map(|&byte| parser.push(byte))
+                        .collect();
+
+                    if !messages.is_empty() {
+                        has_received_messages = true;
+
+                        let mut state = shared.lock();
+                        state.has_received_messages = true;
+                        messages.into_iter().for_each(|message| state.apply(message, timestamp));
+                        drop(state);
+                        shared.notify_all();
+                    } else if !has_received_messages {
+                        shared.lock().has_received_garbage = true;
+                    }
+                },
+                Err(ref error) if Reader::is_transient(error) => thread::sleep(POLL_INTERVAL),
+                Err(_) => break,
+            }
+        }
+
+        // Dropping the subscribers' senders ends their event iterators.
+        let mut state = shared.lock();
+        state.is_disconnected = true;
+        state.subscribers.clear();
+        state.i2c_subscribers.clear();
+        drop(state);
+        shared.notify_all();
+    }
+
+    /// Indicates whether a given read error only means that no bytes were available yet.
+    fn is_transient(error: &io::Error) -> bool {
+        matches!(
+            error.kind(),
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )
+    }
+
+    /// Records whether a given digital pin is in an input mode, which determines whether events
+    /// are produced for it.
+    /// The value of the pin's port is forgotten, until it is reported anew.
+    pub(crate) fn set_input(&self, pin_index: i32, is_input: bool) {
+        let mut state = self.shared.lock();
+
+        if is_input {
+            state.input_pins.insert(pin_index);
+        } else {
+            state.input_pins.remove(&pin_index);
+        }
+
+        state.port_values.remove(&((pin_index as usize / PINS_PER_PORT) as u8));
+    }
+
+    /// The latest value of a given digital pin.
+    /// This call blocks until the pin's port has been reported at least once, or the timeout
+    /// elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if no value was reported in time.
+    /// * `Disconnected`, if the reader stopped before a value was reported.
+    pub(crate) fn digital_value(&self, pin_index: i32, timeout: Duration) -> Result<i32, Error> {
+        let port = (pin_index as usize / PINS_PER_PORT) as u8;
+        let bit = pin_index as usize % PINS_PER_PORT;
+
+        self.wait_for(Some(timeout), |state| {
+            state.port_values.get(&port).map(|value| i32::from((value >> bit) & 1))
+        })
+    }
+
+    /// The latest sample of a given analog channel.
+    /// This call blocks until the channel has been reported at least once, or the timeout elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if no sample was reported in time.
+    /// * `Disconnected`, if the reader stopped before a sample was reported.
+    pub(crate) fn analog_value(&self, channel: u8, timeout: Duration) -> Result<i32, Error> {
+        self.wait_for(Some(timeout), |state| state.analog_values.get(&channel).map(|&value| i32::from(value)))
+    }
+
+    /// The Firmata protocol version, as reported by the Arduino.
+    /// This call blocks until the Arduino has reported its protocol version, or the timeout
+    /// elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if the protocol version was not reported in time.
+    /// * `Disconnected`, if the reader stopped before the protocol version was reported.
+    pub(crate) fn protocol_version(&self, timeout: Duration) -> Result<(u8, u8), Error> {
+        self.wait_for(Some(timeout), |state| state.protocol_version)
+    }
+
+    /// The major and minor version and name of the Arduino's firmware, as reported by the Arduino.
+    /// This call blocks until the Arduino has reported its firmware, or the timeout elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if the firmware was not reported in time.
+    /// * `Disconnected`, if the reader stopped before the firmware was reported.
+    pub(crate) fn firmware(&self, timeout: Duration) -> Result<(u8, u8, String), Error> {
+        self.wait_for(Some(timeout), |state| state.firmware.clone())
+    }
+
+    /// Indicates whether bytes were received, but none of them formed a Firmata message, which
+    /// means that the Arduino is not running Firmata.
+    pub(crate) fn has_only_received_garbage(&self) -> bool {
+        let state = self.shared.lock();
+        state.has_received_garbage && !state.has_received_messages
+    }
+
+    /// The supported modes and their resolutions for each pin, as reported by the Arduino.
+    /// This call blocks until the Arduino has reported its capabilities, or the timeout elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if the capabilities were not reported in time.
+    /// * `Disconnected`, if the reader stopped before the capabilities were reported.
+    pub(crate) fn capabilities(&self, timeout: Duration) -> Result<Vec<Vec<ModeCapability>>, Error> {
+        self.wait_for(Some(timeout), |state| state.capabilities.clone())
+    }
+
+    /// The analog channel of each pin, as reported by the Arduino.
+    /// This call blocks until the Arduino has reported its analog mapping, or the timeout elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if the analog mapping was not reported in time.
+    /// * `Disconnected`, if the reader stopped before the analog mapping was reported.
+    pub(crate) fn analog_mapping(&self, timeout: Duration) -> Result<Vec<Option<u8>>, Error> {
+        self.wait_for(Some(timeout), |state| state.analog_mapping.clone())
+    }
+
+    /// The number of I2C replies received so far, which can be used to wait for a reply received
+    /// after a certain point in time via `i2c_reply`.
+    pub(crate) fn i2c_reply_count(&self) -> u64 {
+        self.shared.lock().i2c_reply_count
+    }
+
+    /// The first reply from a given I2C device and register, which is received after the given
+    /// number of replies.
+    /// This call blocks until such a reply is received or the timeout elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if no reply was received in time.
+    /// * `Disconnected`, if the reader stopped before a reply was received.
+    pub(crate) fn i2c_reply(
+        &self, address: u16, register: u16, after_count: u64, timeout: Duration
+    ) -> Result<I2cReply, Error> {
+        self.wait_for(Some(timeout), |state| {
+            state.i2c_replies.get(&(address, register))
+                .filter(|(count, _)| *count > after_count)
+                .map(|(_, reply)| reply.clone())
+        })
+    }
+
+    /// The number of pin state responses received so far, which can be used to wait for a
+    /// response received after a certain point in time via `pin_state`.
+    pub(crate) fn pin_state_count(&self) -> u64 {
+        self.shared.lock().pin_state_count
+    }
+
+    /// The mode and state of a given pin, from the first pin state response for the pin which is
+    /// received after the given number of responses.
+    /// This call blocks until such a response is received or the timeout elapses.
+    ///
+    /// # Errors
+    /// * `Timeout`, if no response was received in time.
+    /// * `Disconnected`, if the reader stopped before a response was received.
+    pub(crate) fn pin_state(
+        &self, pin_index: u8, after_count: u64, timeout: Duration
+    ) -> Result<(u8, u32), Error> {
+        self.wait_for(Some(timeout), |state| {
+            state.pin_states.get(&pin_index)
+                .filter(|(count, _, _)| *count > after_count)
+                .map(|&(_, mode, pin_state)| (mode, pin_state))
+        })
+    }
+
+    /// Blocks until the given function produces a value for the current state, or the optional
+    /// timeout elapses.
+    fn wait_for<T, F: Fn(&State) -> Option<T>>(
+        &self, timeout: Option<Duration>, value: F
+    ) -> Result<T, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.shared.lock();
+
+        loop {
+            if let Some(value) = value(&state) { return Ok(value); }
+            if state.is_disconnected { return Err(Error::Disconnected); }
+
+            state = match deadline {
+                None => self.shared.updated.wait(state).expect("Reader state lock failed."),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline { return Err(Error::Timeout); }
+
+                    self.shared.updated.wait_timeout(state, deadline - now)
+                        .expect("Reader state lock failed.")
+                        .0
+                },
+            };
+        }
+    }
+
+    /// The latest value of a given digital pin, as with `digital_value`, but without blocking.
+    #[cfg(feature = "async")]
+    pub(crate) async fn digital_value_async(&self, pin_index: i32) -> Result<i32, Error> {
+        let port = (pin_index as usize / PINS_PER_PORT) as u8;
+        let bit = pin_index as usize % PINS_PER_PORT;
+
+        self.wait_for_async(|state| {
+            state.port_values.get(&port).map(|value| i32::from((value >> bit) & 1))
+        }).await
+    }
+
+    /// The latest sample of a given analog channel, as with `analog_value`, but without blocking.
+    #[cfg(feature = "async")]
+    pub(crate) async fn analog_value_async(&self, channel: u8) -> Result<i32, Error> {
+        self.wait_for_async(|state| state.analog_values.get(&channel).map(|&value| i32::from(value))).await
+    }
+
+    /// Waits until the given function produces a value for the current state, as with `wait_for`,
+    /// but without blocking.
+    #[cfg(feature = "async")]
+    async fn wait_for_async<T, F: Fn(&State) -> Option<T>>(&self, value: F) -> Result<T, Error> {
+        loop {
+            // The notification is registered before checking the state, so that no update between
+            // checking and waiting is missed.
+            let updated = self.shared.updated_async.notified();
+            tokio::pin!(updated);
+            updated.as_mut().enable();
+
+            {
+                let state = self.shared.lock();
+                if let Some(value) = value(&state) { return Ok(value); }
+                if state.is_disconnected { return Err(Error::Disconnected); }
+            }
+
+            updated.await;
+        }
+    }
+
+    /// Creates a channel over which events for the given pins are sent.
+    /// If no pins are given, events for all pins are sent.
+    pub(crate) fn subscribe(&self, pins: Option<HashSet<Pin>>) -> mpsc::Receiver<PinEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribe_with(pins, move |event| sender.send(event).is_ok());
+
+        receiver
+    }
+
+    /// Passes events for the given pins to a given function, until it returns `false`.
+    /// If no pins are given, events for all pins are passed.
+    pub(crate) fn subscribe_with<F>(&self, pins: Option<HashSet<Pin>>, send: F)
+    where F: Fn(PinEvent) -> bool + Send + 'static {
+        self.shared.lock().subscribers.push(Subscriber { pins, send: Box::new(send) });
+    }
+
+    /// Creates a channel over which all replies from a given I2C device are sent.
+    pub(crate) fn subscribe_i2c(&self, address: u16) -> mpsc::Receiver<I2cReply> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.lock().i2c_subscribers.push(I2cSubscriber { address, sender });
+
+        receiver
+    }
+}
+
+impl Drop for Reader {
+
+    fn drop(&mut self) {
+        self.shared.lock().is_stopped = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A source of bytes which can be fed from a test, like a serial port would be fed by an
+    /// Arduino.
+    struct TestSource(mpsc::Receiver<Vec<u8>>);
+
+    impl Read for TestSource {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match self.0.try_recv() {
+                Ok(bytes) => {
+                    buffer[..bytes.len()].copy_from_slice(&bytes);
+                    Ok(bytes.len())
+                },
+                Err(mpsc::TryRecvError::Empty) => Err(io::ErrorKind::WouldBlock.into()),
+                Err(mpsc::TryRecvError::Disconnected) => Err(io::ErrorKind::BrokenPipe.into()),
+            }
+        }
+    }
+
+    fn reader() -> (Reader, mpsc::Sender<Vec<u8>>) {
+        let (sender, receiver) = mpsc::channel();
+        (Reader::spawn(TestSource(receiver)), sender)
+    }
+
+    #[test]
+    fn digital_value() {
+        let (reader, source) = reader();
+
+        source.send(vec![0x91, 0x04, 0x00]).unwrap();
+
+        assert_eq!(reader.digital_value(10, Duration::from_secs(1)).unwrap(), 1);
+        assert_eq!(reader.digital_value(9, Duration::from_secs(1)).unwrap(), 0);
+    }
+
+    #[test]
+    fn unreported_digital_value() {
+        let (reader, source) = reader();
+
+        source.send(vec![0x91, 0x04, 0x00]).unwrap();
+
+        assert_eq!(reader.digital_value(2, Duration::from_millis(10)).unwrap_err(), Error::Timeout);
+    }
+
+    #[test]
+    fn analog_value() {
+        let (reader, source) = reader();
+
+        source.send(vec![0xE3, 0x00, 0x04]).unwrap();
+
+        assert_eq!(reader.analog_value(3, Duration::from_secs(1)).unwrap(), 512);
+    }
+
+    #[test]
+    fn unreported_analog_value() {
+        let (reader, source) = reader();
+
+        source.send(vec![0xE3, 0x00, 0x04]).unwrap();
+
+        assert_eq!(reader.analog_value(2, Duration::from_millis(10)).unwrap_err(), Error::Timeout);
+    }
+
+    #[test]
+    fn disconnected() {
+        let (reader, source) = reader();
+
+        drop(source);
+
+        assert_eq!(reader.analog_value(0, Duration::from_secs(1)).unwrap_err(), Error::Disconnected);
+    }
+
+    #[test]
+    fn filtered_subscription() {
+        let (reader, source) = reader();
+        reader.set_input(2, true);
+        reader.set_input(3, true);
+
+        let pins = [Pin::Digital(3), Pin::Analog(0)].iter().cloned().collect();
+        let events = reader.subscribe(Some(pins));
+
+        source.send(vec![0x90, 0x0C, 0x00, 0xE1, 0x10, 0x00, 0xE0, 0x20, 0x00]).unwrap();
+        drop(source);
+
+        let received: Vec<_> = events.iter().map(|event| (event.pin(), event.value())).collect();
+
+        assert_eq!(received, vec![(Pin::Digital(3), 1), (Pin::Analog(0), 32)]);
+    }
+
+    #[test]
+    fn digital_events_only_on_change() {
+        let (reader, source) = reader();
+        reader.set_input(0, true);
+        reader.set_input(1, true);
+
+        let events = reader.subscribe(None);
+
+        source.send(vec![0x90, 0x01, 0x00]).unwrap();
+        source.send(vec![0x90, 0x03, 0x00]).unwrap();
+        drop(source);
+
+        let received: Vec<_> = events.iter().map(|event| (event.pin(), event.value())).collect();
+
+        assert_eq!(received, vec![(Pin::Digital(0), 1), (Pin::Digital(1), 0), (Pin::Digital(1), 1)]);
+    }
+
+    #[test]
+    fn i2c_reply_after_count() {
+        let (reader, source) = reader();
+        let reply = [0xF0, 0x77, 0x48, 0x00, 0x02, 0x00, 0x05, 0x00, 0xF7];
+
+        source.send(reply.to_vec()).unwrap();
+        reader.i2c_reply(0x48, 2, 0, Duration::from_secs(1)).unwrap();
+
+        let count = reader.i2c_reply_count();
+        let err = reader.i2c_reply(0x48, 2, count, Duration::from_millis(10)).unwrap_err();
+
+        assert_eq!(err, Error::Timeout);
+
+        source.send(reply.to_vec()).unwrap();
+        let reply = reader.i2c_reply(0x48, 2, count, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(reply.data(), &[5]);
+    }
+
+    #[test]
+    fn i2c_subscription() {
+        let (reader, source) = reader();
+        let replies = reader.subscribe_i2c(0x48);
+
+        source.send(vec![0xF0, 0x77, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0xF7]).unwrap();
+        source.send(vec![0xF0, 0x77, 0x48, 0x00, 0x00, 0x00, 0x02, 0x00, 0xF7]).unwrap();
+        drop(source);
+
+        let received: Vec<_> = replies.iter().map(|reply| reply.data().to_vec()).collect();
+
+        assert_eq!(received, vec![vec![2]]);
+    }
+
+    #[test]
+    fn pin_state_after_count() {
+        let (reader, source) = reader();
+
+        source.send(vec![0xF0, 0x6E, 0x0D, 0x01, 0x01, 0xF7]).unwrap();
+
+        assert_eq!(reader.pin_state(13, 0, Duration::from_secs(1)), Ok((1, 1)));
+
+        let count = reader.pin_state_count();
+        source.send(vec![0xF0, 0x6E, 0x0D, 0x03, 0x7F, 0x01, 0xF7]).unwrap();
+
+        assert_eq!(reader.pin_state(13, count, Duration::from_secs(1)), Ok((3, 255)));
+    }
+}
@@ -0,0 +1,588 @@
+//! This module provides a codec for the Firmata protocol, which is used to communicate with
+//! Arduinos running StandardFirmata (or other Firmata-based sketches).
+//!
+//! Messages are encoded via `Message::encode` and decoded incrementally by a `Parser`.
+
+use crate::arduino::I2cReply;
+
+pub const DIGITAL_MESSAGE:         u8 = 0x90;
+pub const ANALOG_MESSAGE:          u8 = 0xE0;
+pub const REPORT_ANALOG:           u8 = 0xC0;
+pub const REPORT_DIGITAL:          u8 = 0xD0;
+pub const SET_PIN_MODE:            u8 = 0xF4;
+pub const SET_DIGITAL_PIN_VALUE:   u8 = 0xF5;
+pub const PROTOCOL_VERSION:        u8 = 0xF9;
+pub const SYSTEM_RESET:            u8 = 0xFF;
+pub const START_SYSEX:             u8 = 0xF0;
+pub const END_SYSEX:               u8 = 0xF7;
+
+pub const ANALOG_MAPPING_QUERY:    u8 = 0x69;
+pub const ANALOG_MAPPING_RESPONSE: u8 = 0x6A;
+pub const CAPABILITY_QUERY:        u8 = 0x6B;
+pub const CAPABILITY_RESPONSE:     u8 = 0x6C;
+pub const PIN_STATE_QUERY:         u8 = 0x6D;
+pub const PIN_STATE_RESPONSE:      u8 = 0x6E;
+pub const EXTENDED_ANALOG:         u8 = 0x6F;
+pub const SERVO_CONFIG:            u8 = 0x70;
+pub const STRING_DATA:             u8 = 0x71;
+pub const I2C_REQUEST:             u8 = 0x76;
+pub const I2C_REPLY:               u8 = 0x77;
+pub const I2C_CONFIG:              u8 = 0x78;
+pub const REPORT_FIRMWARE:         u8 = 0x79;
+pub const SAMPLING_INTERVAL:       u8 = 0x7A;
+
+/// In capability and analog mapping responses, this value separates pins or marks a pin as not
+/// being analog.
+const NONE: u8 = 0x7F;
+
+/// The maximum number of data bytes collected for a sysex message. Longer sysex messages are
+/// assumed to be garbage and are dropped.
+const MAX_SYSEX_LENGTH: usize = 4096;
+
+/// A mode supported by a pin, as listed in a capability response.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ModeCapability {
+    pub mode: u8,
+    pub resolution: u8,
+}
+
+/// The modes of an `I2C_REQUEST` sysex message.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum I2cMode {
+    Write            = 0b00,
+    Read             = 0b01,
+    ReadContinuously = 0b10,
+    StopReading      = 0b11,
+}
+
+impl I2cMode {
+
+    fn from_bits(bits: u8) -> I2cMode {
+        match bits & 0b11 {
+            0b00 => I2cMode::Write,
+            0b01 => I2cMode::Read,
+            0b10 => I2cMode::ReadContinuously,
+               _ => I2cMode::StopReading,
+        }
+    }
+}
+
+/// The sender of the messages decoded by a parser, which determines how ambiguous messages are
+/// decoded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Origin {
+    Host,
+    Arduino,
+}
+
+/// A message of the Firmata protocol, sent either by the host or by an Arduino.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    /// The values of a digital port's pins. Sent by the host to write output pins, and by an
+    /// Arduino to report input pins.
+    DigitalPort { port: u8, value: u16 },
+    /// An analog value. Sent by the host to write one of the pins 0 to 15, and by an Arduino to
+    /// report a sample, in which case `pin` is the analog channel.
+    Analog { pin: u8, value: u16 },
+    ReportAnalog { channel: u8, enabled: bool },
+    ReportDigital { port: u8, enabled: bool },
+    SetPinMode { pin: u8, mode: u8 },
+    SetDigitalPinValue { pin: u8, value: bool },
+    ProtocolVersionQuery,
+    ProtocolVersion { major: u8, minor: u8 },
+    SystemReset,
+
+    AnalogMappingQuery,
+    /// The analog channel of each pin, or `None` for non-analog pins.
+    AnalogMapping(Vec<Option<u8>>),
+    CapabilityQuery,
+    /// The supported modes of each pin.
+    Capabilities(Vec<Vec<ModeCapability>>),
+    PinStateQuery { pin: u8 },
+    PinState { pin: u8, mode: u8, state: u32 },
+    /// An analog value for any pin, as written by the host.
+    ExtendedAnalog { pin: u8, value: u32 },
+    ServoConfig { pin: u8, min_pulse: u16, max_pulse: u16 },
+    StringData(String),
+    FirmwareQuery,
+    Firmware { major: u8, minor: u8, name: String },
+    SamplingInterval(u16),
+    I2cConfig { delay: u16 },
+    /// A request to a given I2C device. The data are the bytes to write, or the (optional)
+    /// register and number of bytes to read.
+    I2cRequest { address: u16, mode: I2cMode, data: Vec<u16> },
+    I2cReply(I2cReply),
+    /// A sysex message with an unknown command.
+    Sysex { command: u8, data: Vec<u8> },
+}
+
+impl Message {
+
+    /// Encodes the message into the bytes sent over the wire.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::DigitalPort { port, value } => core(DIGITAL_MESSAGE | (port & 0x0F), &encode_14_bit(*value)),
+            Message::Analog { pin, value } => core(ANALOG_MESSAGE | (pin & 0x0F), &encode_14_bit(*value)),
+            Message::ReportAnalog { channel, enabled } => core(REPORT_ANALOG | (channel & 0x0F), &[(*enabled).into()]),
+            Message::ReportDigital { port, enabled } => core(REPORT_DIGITAL | (port & 0x0F), &[(*enabled).into()]),
+            Message::SetPinMode { pin, mode } => core(SET_PIN_MODE, &[pin & 0x7F, mode & 0x7F]),
+            Message::SetDigitalPinValue { pin, value } => core(SET_DIGITAL_PIN_VALUE, &[pin & 0x7F, (*value).into()]),
+            Message::ProtocolVersionQuery => vec![PROTOCOL_VERSION],
+            Message::ProtocolVersion { major, minor } => core(PROTOCOL_VERSION, &[major & 0x7F, minor & 0x7F]),
+            Message::SystemReset => vec![SYSTEM_RESET],
+
+            Message::AnalogMappingQuery => sysex(ANALOG_MAPPING_QUERY, &[]),
+            Message::AnalogMapping(mapping) => {
+                let data: Vec<u8> = mapping.iter().map(|channel| channel.unwrap_or(NONE)).collect();
+                sysex(ANALOG_MAPPING_RESPONSE, &data)
+            },
+            Message::CapabilityQuery => sysex(CAPABILITY_QUERY, &[]),
+            Message::Capabilities(pins) => {
+                let mut data = vec![];
+                for modes in pins {
+                    modes.iter().for_each(|capability| data.extend(&[capability.mode, capability.resolution]));
+                    data.push(NONE);
+                }
+                sysex(CAPABILITY_RESPONSE, &data)
+            },
+            Message::PinStateQuery { pin } => sysex(PIN_STATE_QUERY, &[*pin]),
+            Message::PinState { pin, mode, state } => {
+                let mut data = vec![*pin, *mode];
+                data.extend(encode_7_bit_chunks(*state));
+                sysex(PIN_STATE_RESPONSE, &data)
+            },
+            Message::ExtendedAnalog { pin, value } => {
+                let mut data = vec![*pin];
+                data.extend(encode_7_bit_chunks(*value));
+                sysex(EXTENDED_ANALOG, &data)
+            },
+            Message::ServoConfig { pin, min_pulse, max_pulse } => {
+                let mut data = vec![*pin];
+                data.extend(&encode_14_bit(*min_pulse));
+                data.extend(&encode_14_bit(*max_pulse));
+                sysex(SERVO_CONFIG, &data)
+            },
+            Message::StringData(string) => sysex(STRING_DATA, &encode_string(string)),
+            Message::FirmwareQuery => sysex(REPORT_FIRMWARE, &[]),
+            Message::Firmware { major, minor, name } => {
+                let mut data = vec![*major, *minor];
+                data.extend(encode_string(name));
+                sysex(REPORT_FIRMWARE, &data)
+            },
+            Message::SamplingInterval(interval) => sysex(SAMPLING_INTERVAL, &encode_14_bit(*interval)),
+            Message::I2cConfig { delay } => sysex(I2C_CONFIG, &encode_14_bit(*delay)),
+            Message::I2cRequest { address, mode, data: values } => {
+                // Addresses above 7 bits use the 10-bit address mode, with the upper address bits
+                // in the mode byte.
+                let is_10_bit = *address > 0x7F;
+                let mode_byte = ((is_10_bit as u8) << 5) | ((*mode as u8) << 3) | ((address >> 7) as u8 & 0b111);
+
+                let mut data = vec![(address & 0x7F) as u8, mode_byte];
+                values.iter().for_each(|&value| data.extend(&encode_14_bit(value)));
+                sysex(I2C_REQUEST, &data)
+            },
+            Message::I2cReply(reply) => {
+                let mut data = vec![];
+                data.extend(&encode_14_bit(reply.address));
+                data.extend(&encode_14_bit(reply.register));
+                reply.data.iter().for_each(|&byte| data.extend(&encode_14_bit(byte.into())));
+                sysex(I2C_REPLY, &data)
+            },
+            Message::Sysex { command, data } => sysex(*command, data),
+        }
+    }
+}
+
+/// An incremental parser for a stream of Firmata messages.
+///
+/// The parser tolerates garbage in the stream: data bytes which do not belong to any message,
+/// unknown commands and messages interrupted by another command are skipped.
+#[derive(Debug)]
+pub struct Parser {
+    origin: Origin,
+    command: Option<u8>,
+    data: Vec<u8>,
+    in_sysex: bool,
+}
+
+impl Default for Parser {
+
+    /// A parser for messages sent by an Arduino.
+    fn default() -> Parser { Parser::new(Origin::Arduino) }
+}
+
+impl Parser {
+
+    /// Creates a parser for messages sent by the given origin.
+    pub fn new(origin: Origin) -> Parser {
+        Parser { origin, command: None, data: vec![], in_sysex: false }
+    }
+
+    /// Feeds a single byte to the parser, returning a message if the byte completes one.
+    pub fn push(&mut self, byte: u8) -> Option<Message> {
+        // Command bytes are the only bytes with their most significant bit set, so they always
+        // start a new message (or end a sysex message).
+        if byte & 0x80 != 0 {
+            let message = if byte == END_SYSEX && self.in_sysex { self.parse_sysex() } else { None };
+
+            self.data.clear();
+            self.in_sysex = byte == START_SYSEX;
+            self.command = None;
+
+            if byte == START_SYSEX || byte == END_SYSEX { return message; }
+
+            return match self.data_length(byte) {
+                Some(0) => self.parse_core(byte),
+                Some(_) => { self.command = Some(byte); None },
+                None => None,
+            };
+        }
+
+        if self.in_sysex {
+            if self.data.len() < MAX_SYSEX_LENGTH {
+                self.data.push(byte);
+            } else {
+                self.in_sysex = false;
+                self.data.clear();
+            }
+
+            return None;
+        }
+
+        let command = self.command?;
+        self.data.push(byte);
+
+        if Some(self.data.len()) < self.data_length(command) { return None; }
+
+        let message = self.parse_core(command);
+        self.data.clear();
+        self.command = None;
+
+        message
+    }
+
+    /// The number of data bytes following a given (non-sysex) command, or `None` if the command
+    /// is unknown.
+    fn data_length(&self, command: u8) -> Option<usize> {
+        match (command & 0xF0, command) {
+            (DIGITAL_MESSAGE, _) | (ANALOG_MESSAGE, _) => Some(2),
+            (REPORT_ANALOG, _) | (REPORT_DIGITAL, _) => Some(1),
+            (_, SET_PIN_MODE) | (_, SET_DIGITAL_PIN_VALUE) => Some(2),
+            (_, PROTOCOL_VERSION) => Some(if self.origin == Origin::Host { 0 } else { 2 }),
+            (_, SYSTEM_RESET) => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Parses the collected data of a (non-sysex) message with the given command.
+    fn parse_core(&self, command: u8) -> Option<Message> {
+        let data = &self.data;
+
+        let message = match (command & 0xF0, command) {
+            (DIGITAL_MESSAGE, _) => Message::DigitalPort { port: command & 0x0F, value: decode_14_bit(data) },
+            (ANALOG_MESSAGE, _) => Message::Analog { pin: command & 0x0F, value: decode_14_bit(data) },
+            (REPORT_ANALOG, _) => Message::ReportAnalog { channel: command & 0x0F, enabled: data[0] != 0 },
+            (REPORT_DIGITAL, _) => Message::ReportDigital { port: command & 0x0F, enabled: data[0] != 0 },
+            (_, SET_PIN_MODE) => Message::SetPinMode { pin: data[0], mode: data[1] },
+            (_, SET_DIGITAL_PIN_VALUE) => Message::SetDigitalPinValue { pin: data[0], value: data[1] != 0 },
+            (_, PROTOCOL_VERSION) if data.is_empty() => Message::ProtocolVersionQuery,
+            (_, PROTOCOL_VERSION) => Message::ProtocolVersion { major: data[0], minor: data[1] },
+            (_, SYSTEM_RESET) => Message::SystemReset,
+            _ => return None,
+        };
+
+        Some(message)
+    }
+
+    /// Parses the collected data of a sysex message, whose first byte is the sysex command.
+    fn parse_sysex(&self) -> Option<Message> {
+        let (&command, data) = self.data.split_first()?;
+        let values: Vec<u16> = data.chunks_exact(2).map(decode_14_bit).collect();
+
+        let message = match command {
+            ANALOG_MAPPING_QUERY => Message::AnalogMappingQuery,
+            ANALOG_MAPPING_RESPONSE => Message::AnalogMapping(data.iter()
+                .map(|&channel| if channel == NONE { None } else { Some(channel) })
+                .collect()),
+            CAPABILITY_QUERY => Message::CapabilityQuery,
+            CAPABILITY_RESPONSE => {
+                // Each pin's modes are listed as pairs of mode and resolution, terminated by `NONE`.
+                let mut pins = data.split(|&byte| byte == NONE).collect::<Vec<_>>();
+                pins.pop();
+
+                Message::Capabilities(pins.into_iter()
+                    .map(|modes| modes.chunks_exact(2)
+                        .map(|mode| ModeCapability { mode: mode[0], resolution: mode[1] })
+                        .collect())
+                    .collect())
+            },
+            PIN_STATE_QUERY if !data.is_empty() => Message::PinStateQuery { pin: data[0] },
+            PIN_STATE_RESPONSE if data.len() >= 2 => Message::PinState {
+                pin: data[0], mode: data[1], state: decode_7_bit_chunks(&data[2..]),
+            },
+            EXTENDED_ANALOG if !data.is_empty() => Message::ExtendedAnalog {
+                pin: data[0], value: decode_7_bit_chunks(&data[1..]),
+            },
+            SERVO_CONFIG if data.len() >= 5 => Message::ServoConfig {
+                pin: data[0], min_pulse: decode_14_bit(&data[1..3]), max_pulse: decode_14_bit(&data[3..5]),
+            },
+            STRING_DATA => Message::StringData(decode_string(data)),
+            REPORT_FIRMWARE if data.is_empty() => Message::FirmwareQuery,
+            REPORT_FIRMWARE if data.len() >= 2 => Message::Firmware {
+                major: data[0], minor: data[1], name: decode_string(&data[2..]),
+            },
+            SAMPLING_INTERVAL if values.len() == 1 => Message::SamplingInterval(values[0]),
+            I2C_CONFIG if !values.is_empty() => Message::I2cConfig { delay: values[0] },
+            I2C_REQUEST if data.len() >= 2 => Message::I2cRequest {
+                address: u16::from(data[0]) | u16::from(data[1] & 0b111) << 7,
+                mode: I2cMode::from_bits(data[1] >> 3),
+                data: data[2..].chunks_exact(2).map(decode_14_bit).collect(),
+            },
+            I2C_REPLY if values.len() >= 2 => Message::I2cReply(I2cReply {
+                address: values[0],
+                register: values[1],
+                data: values[2..].iter().map(|&value| value as u8).collect(),
+            }),
+            // Known sysex messages with malformed data are dropped.
+            PIN_STATE_QUERY | PIN_STATE_RESPONSE | EXTENDED_ANALOG | SERVO_CONFIG | REPORT_FIRMWARE |
+            SAMPLING_INTERVAL | I2C_CONFIG | I2C_REQUEST | I2C_REPLY => return None,
+            _ => Message::Sysex { command, data: data.to_vec() },
+        };
+
+        Some(message)
+    }
+}
+
+/// Encodes a (non-sysex) message with the given command and data bytes.
+fn core(command: u8, data: &[u8]) -> Vec<u8> {
+    let mut message = vec![command];
+    message.extend_from_slice(data);
+
+    message
+}
+
+/// Encodes a sysex message with the given command and data bytes.
+fn sysex(command: u8, data: &[u8]) -> Vec<u8> {
+    let mut message = vec![START_SYSEX, command];
+    message.extend(data.iter().map(|byte| byte & 0x7F));
+    message.push(END_SYSEX);
+
+    message
+}
+
+/// Splits a 14-bit value into two 7-bit data bytes, least significant byte first.
+fn encode_14_bit(value: u16) -> [u8; 2] {
+    [(value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8]
+}
+
+/// Combines two 7-bit data bytes, least significant byte first, into a 14-bit value.
+fn decode_14_bit(bytes: &[u8]) -> u16 {
+    u16::from(bytes[0]) | u16::from(bytes[1]) << 7
+}
+
+/// Splits a value into as many 7-bit data bytes as needed (but at least one), least significant
+/// byte first.
+fn encode_7_bit_chunks(mut value: u32) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+
+    while value != 0 {
+        bytes.push((value & 0x7F) as u8);
+        value >>= 7;
+    }
+
+    bytes
+}
+
+/// Combines up to five 7-bit data bytes, least significant byte first, into a value.
+fn decode_7_bit_chunks(bytes: &[u8]) -> u32 {
+    bytes.iter()
+        .take(5)
+        .enumerate()
+        .fold(0, |value, (index, &byte)| value | u32::from(byte) << (7 * index))
+}
+
+/// Encodes a string with each character split into two 7-bit data bytes.
+fn encode_string(string: &str) -> Vec<u8> {
+    string.chars()
+        .flat_map(|character| encode_14_bit(character as u16).to_vec())
+        .collect()
+}
+
+/// Decodes a string with each character split into two 7-bit data bytes.
+fn decode_string(bytes: &[u8]) -> String {
+    bytes.chunks_exact(2)
+        .filter_map(|bytes| char::from_u32(decode_14_bit(bytes).into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Message> {
+        let mut parser = Parser::default();
+        bytes.iter().filter_map(|&byte| parser.push(byte)).collect()
+    }
+
+    /// Encodes a given message and parses it again, as sent by the given origin.
+    fn round_trip(message: Message, origin: Origin) -> Vec<Message> {
+        let mut parser = Parser::new(origin);
+        message.encode().into_iter().filter_map(|byte| parser.push(byte)).collect()
+    }
+
+    #[test]
+    fn digital_message() {
+        let messages = parse(&[0x91, 0x05, 0x01]);
+
+        assert_eq!(messages, vec![Message::DigitalPort { port: 1, value: 0x85 }]);
+    }
+
+    #[test]
+    fn analog_message() {
+        let messages = parse(&[0xE2, 0x7F, 0x07]);
+
+        assert_eq!(messages, vec![Message::Analog { pin: 2, value: 1023 }]);
+    }
+
+    #[test]
+    fn protocol_version_and_firmware() {
+        let messages = parse(&[0xF9, 0x02, 0x05, 0xF0, 0x79, 0x02, 0x05, 0x41, 0x00, 0xF7]);
+        let firmware = Message::Firmware { major: 2, minor: 5, name: String::from("A") };
+
+        assert_eq!(messages, vec![Message::ProtocolVersion { major: 2, minor: 5 }, firmware]);
+    }
+
+    #[test]
+    fn skips_stray_bytes() {
+        let messages = parse(&[0x12, 0x34, 0x90, 0x01, 0xE1, 0x03, 0x00]);
+
+        assert_eq!(messages, vec![Message::Analog { pin: 1, value: 3 }]);
+    }
+
+    #[test]
+    fn skips_unknown_commands() {
+        let messages = parse(&[0xF1, 0x01, 0x02, 0xE0, 0x04, 0x00]);
+
+        assert_eq!(messages, vec![Message::Analog { pin: 0, value: 4 }]);
+    }
+
+    #[test]
+    fn interrupted_sysex() {
+        let messages = parse(&[0xF0, 0x77, 0x48, 0x00, 0xE0, 0x05, 0x00, 0xF7]);
+
+        assert_eq!(messages, vec![Message::Analog { pin: 0, value: 5 }]);
+    }
+
+    #[test]
+    fn overlong_sysex() {
+        let mut bytes = vec![0xF0, 0x71];
+        bytes.extend(vec![0x41; MAX_SYSEX_LENGTH]);
+        bytes.extend(&[0xF7, 0xE0, 0x01, 0x00]);
+
+        let messages = parse(&bytes);
+
+        assert_eq!(messages, vec![Message::Analog { pin: 0, value: 1 }]);
+    }
+
+    #[test]
+    fn i2c_reply() {
+        let messages = parse(&[0xF0, 0x77, 0x48, 0x00, 0x02, 0x00, 0x7F, 0x01, 0x10, 0x00, 0xF7]);
+        let reply = I2cReply { address: 0x48, register: 2, data: vec![0xFF, 0x10] };
+
+        assert_eq!(messages, vec![Message::I2cReply(reply)]);
+    }
+
+    #[test]
+    fn capability_response() {
+        let messages = parse(&[0xF0, 0x6C, 0x7F, 0x00, 0x01, 0x03, 0x08, 0x7F, 0x02, 0x0A, 0x7F, 0xF7]);
+        let capabilities = vec![
+            vec![],
+            vec![ModeCapability { mode: 0, resolution: 1 }, ModeCapability { mode: 3, resolution: 8 }],
+            vec![ModeCapability { mode: 2, resolution: 10 }],
+        ];
+
+        assert_eq!(messages, vec![Message::Capabilities(capabilities)]);
+    }
+
+    #[test]
+    fn analog_mapping_response() {
+        let messages = parse(&[0xF0, 0x6A, 0x7F, 0x7F, 0x00, 0x01, 0xF7]);
+        let mapping = vec![None, None, Some(0), Some(1)];
+
+        assert_eq!(messages, vec![Message::AnalogMapping(mapping)]);
+    }
+
+    #[test]
+    fn unknown_sysex() {
+        let messages = parse(&[0xF0, 0x01, 0x02, 0x03, 0xF7]);
+
+        assert_eq!(messages, vec![Message::Sysex { command: 0x01, data: vec![0x02, 0x03] }]);
+    }
+
+    #[test]
+    fn encodes_servo_config() {
+        let message = Message::ServoConfig { pin: 9, min_pulse: 544, max_pulse: 2400 }.encode();
+
+        assert_eq!(message, vec![0xF0, 0x70, 9, 0x20, 0x04, 0x60, 0x12, 0xF7]);
+    }
+
+    #[test]
+    fn encodes_extended_analog() {
+        let message = Message::ExtendedAnalog { pin: 44, value: 255 }.encode();
+
+        assert_eq!(message, vec![0xF0, 0x6F, 44, 0x7F, 0x01, 0xF7]);
+    }
+
+    #[test]
+    fn encodes_i2c_read_request() {
+        let request = Message::I2cRequest { address: 0x48, mode: I2cMode::Read, data: vec![0x02, 300] };
+
+        assert_eq!(request.encode(), vec![0xF0, 0x76, 0x48, 0x08, 0x02, 0x00, 0x2C, 0x02, 0xF7]);
+    }
+
+    #[test]
+    fn round_trips_host_messages() {
+        let messages = vec![
+            Message::DigitalPort { port: 2, value: 0xFF },
+            Message::Analog { pin: 3, value: 1000 },
+            Message::ReportAnalog { channel: 1, enabled: true },
+            Message::ReportDigital { port: 0, enabled: false },
+            Message::SetPinMode { pin: 13, mode: 1 },
+            Message::SetDigitalPinValue { pin: 7, value: true },
+            Message::ProtocolVersionQuery,
+            Message::SystemReset,
+            Message::AnalogMappingQuery,
+            Message::CapabilityQuery,
+            Message::PinStateQuery { pin: 4 },
+            Message::ExtendedAnalog { pin: 50, value: 0x1_2345 },
+            Message::ServoConfig { pin: 9, min_pulse: 600, max_pulse: 2400 },
+            Message::FirmwareQuery,
+            Message::SamplingInterval(100),
+            Message::I2cConfig { delay: 10 },
+            Message::I2cRequest { address: 0x3FF, mode: I2cMode::ReadContinuously, data: vec![1, 2] },
+        ];
+
+        for message in messages {
+            assert_eq!(round_trip(message.clone(), Origin::Host), vec![message]);
+        }
+    }
+
+    #[test]
+    fn round_trips_arduino_messages() {
+        let messages = vec![
+            Message::ProtocolVersion { major: 2, minor: 5 },
+            Message::AnalogMapping(vec![None, Some(0), Some(5)]),
+            Message::Capabilities(vec![vec![], vec![ModeCapability { mode: 4, resolution: 14 }]]),
+            Message::PinState { pin: 3, mode: 3, state: 200 },
+            Message::StringData(String::from("Hello")),
+            Message::Firmware { major: 2, minor: 5, name: String::from("StandardFirmata.ino") },
+            Message::I2cReply(I2cReply { address: 0x48, register: 1, data: vec![0, 128, 255] }),
+            Message::Sysex { command: 0x10, data: vec![1, 2, 3] },
+        ];
+
+        for message in messages {
+            assert_eq!(round_trip(message.clone(), Origin::Arduino), vec![message]);
+        }
+    }
+}
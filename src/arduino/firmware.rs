@@ -0,0 +1,28 @@
+/// The oldest Firmata protocol version which is considered up to date by
+/// `Arduino::connect_or_flash`.
+pub const MINIMUM_PROTOCOL_VERSION: (u8, u8) = (2, 5);
+
+/// The firmware running on an Arduino, as reported during the Firmata handshake.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Firmware {
+    pub(crate) name: String,
+    pub(crate) version: (u8, u8),
+    pub(crate) protocol_version: (u8, u8),
+}
+
+impl Firmware {
+
+    /// The name of the firmware, which is usually the file name of its sketch (e.g.
+    /// `StandardFirmata.ino`).
+    pub fn name(&self) -> &str { &self.name }
+
+    /// The major and minor version of the firmware.
+    pub fn version(&self) -> (u8, u8) { self.version }
+
+    /// The major and minor version of the Firmata protocol implemented by the firmware.
+    pub fn protocol_version(&self) -> (u8, u8) { self.protocol_version }
+
+    /// Indicates whether the firmware implements a protocol version older than
+    /// `MINIMUM_PROTOCOL_VERSION`.
+    pub fn is_outdated(&self) -> bool { self.protocol_version < MINIMUM_PROTOCOL_VERSION }
+}
@@ -0,0 +1,298 @@
+//! This module provides implementations of the `embedded-hal` traits for an Arduino's pins and
+//! buses, so that `embedded-hal` device drivers can be run on the host against a real board.
+//!
+//! The module is only available with the `embedded-hal` feature enabled.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::{delay, digital, i2c, pwm};
+
+use crate::arduino::{Arduino, Error, PinMode};
+
+impl digital::Error for Error {
+    fn kind(&self) -> digital::ErrorKind { digital::ErrorKind::Other }
+}
+
+impl pwm::Error for Error {
+    fn kind(&self) -> pwm::ErrorKind { pwm::ErrorKind::Other }
+}
+
+impl i2c::Error for Error {
+    fn kind(&self) -> i2c::ErrorKind { i2c::ErrorKind::Other }
+}
+
+/// An Arduino which can hand out multiple pins and buses at once, by sharing access to it.
+#[derive(Clone)]
+pub struct SharedArduino {
+    arduino: Arc<Mutex<Arduino>>,
+}
+
+impl SharedArduino {
+
+    /// Wraps a given Arduino for shared access.
+    pub fn new(arduino: Arduino) -> SharedArduino {
+        SharedArduino { arduino: Arc::new(Mutex::new(arduino)) }
+    }
+
+    /// Gives exclusive access to the underlying Arduino, until the returned guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Arduino> {
+        self.arduino.lock().expect("Arduino lock failed.")
+    }
+
+    /// Sets a given pin to `DigitalOutput` mode and returns it as an output pin.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have a digital pin at the given index.
+    /// * `InvalidMode`, if the pin does not support `DigitalOutput` mode.
+    pub fn output_pin(&self, pin_index: i32) -> Result<OutputPin, Error> {
+        self.lock().set_pin_mode(pin_index, PinMode::DigitalOutput)?;
+        Ok(OutputPin { arduino: self.clone(), pin_index })
+    }
+
+    /// Sets a given pin to `DigitalInput` or `InputPullup` mode and returns it as an input pin.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have a digital pin at the given index.
+    /// * `InvalidMode`, if the pin does not support the input mode.
+    pub fn input_pin(&self, pin_index: i32, pull_up: bool) -> Result<InputPin, Error> {
+        let mode = if pull_up { PinMode::InputPullup } else { PinMode::DigitalInput };
+
+        self.lock().set_pin_mode(pin_index, mode)?;
+        Ok(InputPin { arduino: self.clone(), pin_index })
+    }
+
+    /// Sets a given pin to `Pwm` mode and returns it as a PWM pin.
+    ///
+    /// # Errors
+    /// * `InvalidPinIndex`, if the Arduino does not have a digital pin at the given index.
+    /// * `InvalidMode`, if the pin does not support `Pwm` mode.
+    pub fn pwm_pin(&self, pin_index: i32) -> Result<PwmPin, Error> {
+        let mut arduino = self.lock();
+        arduino.set_pin_mode(pin_index, PinMode::Pwm)?;
+
+        let max_duty_cycle = (arduino.digital_pins()[pin_index as usize].valid_values().end - 1) as u16;
+
+        Ok(PwmPin { arduino: self.clone(), pin_index, max_duty_cycle })
+    }
+
+    /// The Arduino's I2C bus.
+    pub fn i2c(&self) -> I2c {
+        I2c { arduino: self.clone() }
+    }
+}
+
+/// A digital output pin of a shared Arduino.
+pub struct OutputPin {
+    arduino: SharedArduino,
+    pin_index: i32,
+}
+
+impl digital::ErrorType for OutputPin {
+    type Error = Error;
+}
+
+impl digital::OutputPin for OutputPin {
+
+    fn set_low(&mut self) -> Result<(), Error> {
+        self.arduino.lock().write(self.pin_index, 0)
+    }
+
+    fn set_high(&mut self) -> Result<(), Error> {
+        self.arduino.lock().write(self.pin_index, 1)
+    }
+}
+
+/// A digital input pin of a shared Arduino.
+pub struct InputPin {
+    arduino: SharedArduino,
+    pin_index: i32,
+}
+
+impl digital::ErrorType for InputPin {
+    type Error = Error;
+}
+
+impl digital::InputPin for InputPin {
+
+    fn is_high(&mut self) -> Result<bool, Error> {
+        self.arduino.lock().read_digital(self.pin_index).map(|value| value != 0)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Error> {
+        self.is_high().map(|is_high| !is_high)
+    }
+}
+
+/// A PWM pin of a shared Arduino.
+pub struct PwmPin {
+    arduino: SharedArduino,
+    pin_index: i32,
+    max_duty_cycle: u16,
+}
+
+impl pwm::ErrorType for PwmPin {
+    type Error = Error;
+}
+
+impl pwm::SetDutyCycle for PwmPin {
+
+    fn max_duty_cycle(&self) -> u16 { self.max_duty_cycle }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Error> {
+        self.arduino.lock().write(self.pin_index, duty.into())
+    }
+}
+
+/// The I2C bus of a shared Arduino.
+///
+/// Firmata does not support repeated starts, so the operations of a transaction are performed as
+/// separate requests. A write of a single byte followed by a read is performed as a register read.
+///
+/// A read fails with `I2cReplyLength`, if the device replies with fewer or more bytes than requested.
+pub struct I2c {
+    arduino: SharedArduino,
+}
+
+impl i2c::ErrorType for I2c {
+    type Error = Error;
+}
+
+impl i2c::I2c for I2c {
+
+    fn transaction(
+        &mut self, address: u8, operations: &mut [i2c::Operation<'_>]
+    ) -> Result<(), Error> {
+        let mut arduino = self.arduino.lock();
+        let mut register: Option<u8> = None;
+        let mut operations = operations.iter_mut().peekable();
+
+        while let Some(operation) = operations.next() {
+            match operation {
+                i2c::Operation::Write(bytes) => {
+                    let is_register_write = bytes.len() == 1 &&
+                        matches!(operations.peek(), Some(i2c::Operation::Read(_)));
+
+                    if is_register_write {
+                        register = Some(bytes[0]);
+                    } else {
+                        arduino.i2c_write(address, bytes)?;
+                    }
+                },
+                i2c::Operation::Read(buffer) => {
+                    let len = buffer.len() as u16;
+                    let reply = arduino.i2c_read_from(address, register.take(), len)?;
+
+                    if reply.data().len() != buffer.len() {
+                        return Err(Error::I2cReplyLength {
+                            expected: buffer.len(), actual: reply.data().len(),
+                        });
+                    }
+
+                    buffer.copy_from_slice(reply.data());
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A delay which blocks the current thread of the host.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Delay;
+
+impl delay::DelayNs for Delay {
+
+    fn delay_ns(&mut self, ns: u32) {
+        thread::sleep(Duration::from_nanos(ns.into()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::digital::{InputPin as _, OutputPin as _};
+    use embedded_hal::i2c::I2c as _;
+    use embedded_hal::pwm::SetDutyCycle;
+
+    use crate::arduino::sim::{VirtualBoard, Layout, Input};
+
+    fn connect() -> (VirtualBoard, SharedArduino) {
+        let board = VirtualBoard::new(Layout::uno());
+        let arduino = Arduino::connect_transport(board.transport(), Duration::from_secs(1)).unwrap();
+
+        (board, SharedArduino::new(arduino))
+    }
+
+    #[test]
+    fn output_pin() {
+        let (board, arduino) = connect();
+        let mut pin = arduino.output_pin(13).unwrap();
+
+        pin.set_high().unwrap();
+        assert_eq!(board.pin_value(13), Some(1));
+
+        pin.set_low().unwrap();
+        assert_eq!(board.pin_value(13), Some(0));
+    }
+
+    #[test]
+    fn input_pin() {
+        let (board, arduino) = connect();
+
+        board.apply(Input::Digital { pin: 4, value: true });
+        let mut pin = arduino.input_pin(4, false).unwrap();
+
+        assert_eq!(board.pin_mode(4), Some(PinMode::DigitalInput));
+        assert_eq!(pin.is_high(), Ok(true));
+        assert_eq!(pin.is_low(), Ok(false));
+    }
+
+    #[test]
+    fn pwm_pin() {
+        let (board, arduino) = connect();
+        let mut pin = arduino.pwm_pin(9).unwrap();
+
+        assert_eq!(pin.max_duty_cycle(), 255);
+
+        pin.set_duty_cycle(128).unwrap();
+
+        assert_eq!(board.pin_mode(9), Some(PinMode::Pwm));
+        assert_eq!(board.pin_value(9), Some(128));
+    }
+
+    #[test]
+    fn i2c_register_read() {
+        let (board, arduino) = connect();
+        let mut buffer = [0; 2];
+
+        board.set_i2c_register(0x48, 2, &[0x12, 0x34]);
+        arduino.i2c().write_read(0x48, &[2], &mut buffer).unwrap();
+
+        assert_eq!(buffer, [0x12, 0x34]);
+    }
+
+    #[test]
+    fn i2c_write() {
+        let (board, arduino) = connect();
+
+        arduino.i2c().write(0x48, &[3, 0xAB, 0xCD]).unwrap();
+
+        assert_eq!(board.i2c_register(0x48, 3), Some(vec![0xAB, 0xCD]));
+    }
+
+    #[test]
+    fn i2c_short_reply() {
+        let (board, arduino) = connect();
+        let mut buffer = [0; 2];
+
+        board.set_i2c_register(0x48, 2, &[0x12, 0x34]);
+        board.set_i2c_reply_limit(0x48, 1);
+
+        let result = arduino.i2c().write_read(0x48, &[2], &mut buffer);
+
+        assert_eq!(result, Err(Error::I2cReplyLength { expected: 2, actual: 1 }));
+    }
+}
@@ -0,0 +1,25 @@
+/// A reply sent by an Arduino in response to reading from an I2C device.
+#[derive(Clone, PartialEq, Debug)]
+pub struct I2cReply {
+    pub(crate) address: u16,
+    pub(crate) register: u16,
+    pub(crate) data: Vec<u8>,
+}
+
+impl I2cReply {
+
+    /// Creates a reply with the given bytes read from a register of the device with a given
+    /// address, e.g. for encoding it via `firmata::Message::I2cReply`.
+    pub fn new(address: u16, register: u16, data: Vec<u8>) -> I2cReply {
+        I2cReply { address, register, data }
+    }
+
+    /// The address of the device which was read from.
+    pub fn address(&self) -> u16 { self.address }
+
+    /// The register which was read from.
+    pub fn register(&self) -> u16 { self.register }
+
+    /// The bytes which were read.
+    pub fn data(&self) -> &[u8] { &self.data }
+}
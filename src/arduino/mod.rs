@@ -1,12 +1,53 @@
 mod board;
 pub use board::*;
 
+mod event;
+pub use event::*;
+
+mod servo;
+pub use servo::Servo;
+
+mod i2c;
+pub use i2c::*;
+
+mod firmware;
+pub use firmware::*;
+
+mod transport;
+pub use transport::*;
+
+pub mod firmata;
+
+pub mod sim;
+
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::AsyncArduino;
+
+use std::convert::TryFrom;
 use std::ops::Range;
 
+use crate::arduino::firmata::ModeCapability;
+
+/// A pin as described by an Arduino's capability and analog mapping responses, along with the raw
+/// mode and value last written to it.
+#[derive(Clone, Debug)]
+pub(crate) struct FirmataPin {
+    pub(crate) modes: Vec<ModeCapability>,
+    pub(crate) analog_channel: Option<u8>,
+    pub(crate) value: i32,
+    pub(crate) mode: u8,
+}
+
 /// A digital pin on an Arduino.
 #[derive(Clone, Debug)]
 pub struct DigitalPin {
     mode: PinMode,
+    value: i32,
     bit_resolution: u8,
     valid_modes: Vec<PinMode>,
 }
@@ -16,17 +57,22 @@ impl DigitalPin {
     /// The mode of the pin on the source Arduino (at the time that this instance was retrieved).
     pub fn mode(&self) -> PinMode { self.mode }
 
+    /// The value last written to the pin, or reported for it by the Arduino when its pin states
+    /// were last synchronized (at the time that this instance was retrieved).
+    pub fn value(&self) -> i32 { self.value }
+
     /// The range of values which are valid for the pin in its current mode.
     pub fn valid_values(&self) -> Range<i32> {
         0..(2i32.pow(self.bit_resolution as u32))
     }
 
-    /// Constructs a digital pin instance from a non-analog (digital) `firmata::Pin`.
+    /// Constructs a digital pin instance from a non-analog (digital) `FirmataPin`.
     ///
-    /// # Panics
-    /// * should never panic, but could if there is an implementation error.
-    fn from_digital(firmata_pin: &firmata::Pin) -> DigitalPin {
-        let mode = PinMode::from(firmata_pin.mode);
+    /// # Errors
+    /// * `UnknownPinMode`, if the pin's mode or one of its supported modes is unknown, or if its
+    ///   mode is inconsistent with its supported modes.
+    fn from_digital(firmata_pin: &FirmataPin) -> Result<DigitalPin, Error> {
+        let mode = PinMode::try_from(firmata_pin.mode)?;
         let mut valid_modes: Vec<PinMode> = vec![];
         let mut bit_resolution: Option<u8> = None;
 
@@ -35,23 +81,66 @@ impl DigitalPin {
                 if bit_resolution.is_none() {
                     bit_resolution = Some(firmata_mode.resolution);
                 } else {
-                    panic!("Internal inconsistency between arduino::DigitalPin and firmata::Pin");
+                    return Err(Error::UnknownPinMode(firmata_pin.mode));
                 }
             }
 
-            valid_modes.push(PinMode::from(firmata_mode.mode));
+            valid_modes.push(PinMode::try_from(firmata_mode.mode)?);
         }
 
         if valid_modes.is_empty() { bit_resolution = Some(0); }
 
         if let Some(bit_resolution) = bit_resolution {
-            DigitalPin { mode, bit_resolution, valid_modes }
+            Ok(DigitalPin { mode, value: firmata_pin.value, bit_resolution, valid_modes })
         } else {
-            panic!("Internal inconsistency between arduino::DigitalPin and firmata::Pin");
+            Err(Error::UnknownPinMode(firmata_pin.mode))
         }
     }
 }
 
+/// An analog input pin on an Arduino.
+#[derive(Clone, Debug)]
+pub struct AnalogPin {
+    channel: u8,
+    pin_index: usize,
+    bit_resolution: u8,
+    value: i32,
+}
+
+impl AnalogPin {
+
+    /// The analog channel of the pin, i.e. `0` for pin A0, `1` for pin A1, etc.
+    pub fn channel(&self) -> u8 { self.channel }
+
+    /// The most recent value read from the pin (at the time that this instance was retrieved).
+    /// This is `0` if the pin has never been read.
+    pub fn value(&self) -> i32 { self.value }
+
+    /// The number of bits used by the pin's analog-to-digital converter.
+    pub fn bit_resolution(&self) -> u8 { self.bit_resolution }
+
+    /// The range of values which can be read from the pin.
+    pub fn valid_values(&self) -> Range<i32> {
+        0..(2i32.pow(self.bit_resolution as u32))
+    }
+
+    /// Constructs an analog pin instance from an analog `FirmataPin`, which is located at the
+    /// given index in the board's pin list.
+    ///
+    /// # Errors
+    /// * `UnknownPinMode`, if the pin does not support `AnalogInput` mode.
+    fn from_analog(
+        channel: u8, pin_index: usize, firmata_pin: &FirmataPin
+    ) -> Result<AnalogPin, Error> {
+        let bit_resolution = firmata_pin.modes.iter()
+            .find(|firmata_mode| firmata_mode.mode == PinMode::AnalogInput as u8)
+            .map(|firmata_mode| firmata_mode.resolution)
+            .ok_or(Error::UnknownPinMode(PinMode::AnalogInput as u8))?;
+
+        Ok(AnalogPin { channel, pin_index, bit_resolution, value: 0 })
+    }
+}
+
 /// The mode of a pin on an Arduino.
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum PinMode {
@@ -69,27 +158,29 @@ pub enum PinMode {
     InputPullup   = 0xB,
 }
 
-impl PinMode {
+impl TryFrom<u8> for PinMode {
+    type Error = Error;
 
     /// Constructs a pin mode from its raw value.
     ///
-    /// # Panics
-    /// * if the given value does not correspond to one of the raw values of the enum's variants.
-    fn from(value: u8) -> PinMode {
+    /// # Errors
+    /// * `UnknownPinMode`, if the given value does not correspond to one of the raw values of the
+    ///   enum's variants.
+    fn try_from(value: u8) -> Result<PinMode, Error> {
         match value {
-            0x0 => PinMode::DigitalInput ,
-            0x1 => PinMode::DigitalOutput,
-            0x2 => PinMode::AnalogInput,
-            0x3 => PinMode::Pwm,
-            0x4 => PinMode::Servo,
-            0x5 => PinMode::Shift,
-            0x6 => PinMode::I2c,
-            0x7 => PinMode::OneWire,
-            0x8 => PinMode::Stepper,
-            0x9 => PinMode::Encoder,
-            0xA => PinMode::Serial,
-            0xB => PinMode::InputPullup,
-              _ => panic!(format!("PinMode can not be constructed from value '{}'", value)),
+            0x0 => Ok(PinMode::DigitalInput),
+            0x1 => Ok(PinMode::DigitalOutput),
+            0x2 => Ok(PinMode::AnalogInput),
+            0x3 => Ok(PinMode::Pwm),
+            0x4 => Ok(PinMode::Servo),
+            0x5 => Ok(PinMode::Shift),
+            0x6 => Ok(PinMode::I2c),
+            0x7 => Ok(PinMode::OneWire),
+            0x8 => Ok(PinMode::Stepper),
+            0x9 => Ok(PinMode::Encoder),
+            0xA => Ok(PinMode::Serial),
+            0xB => Ok(PinMode::InputPullup),
+              _ => Err(Error::UnknownPinMode(value)),
         }
     }
 }
@@ -100,15 +191,61 @@ mod tests {
 
     #[test]
     fn valid_pin_value() {
-        let pin = DigitalPin { mode: PinMode::Pwm, bit_resolution: 10, valid_modes: vec![] };
+        let pin = DigitalPin { mode: PinMode::Pwm, value: 0, bit_resolution: 10, valid_modes: vec![] };
 
         assert_eq!(pin.valid_values(), 0..1024);
     }
 
     #[test]
     fn invalid_pin_value() {
-        let pin = DigitalPin { mode: PinMode::Serial, bit_resolution: 1, valid_modes: vec![] };
+        let pin = DigitalPin { mode: PinMode::Serial, value: 0, bit_resolution: 1, valid_modes: vec![] };
 
         assert!(!pin.valid_values().contains(&2));
     }
+
+    #[test]
+    fn valid_analog_pin_value() {
+        let pin = AnalogPin { channel: 0, pin_index: 14, bit_resolution: 10, value: 0 };
+
+        assert_eq!(pin.valid_values(), 0..1024);
+    }
+
+    #[test]
+    fn analog_pin_from_firmata() {
+        let firmata_pin = FirmataPin {
+            modes: vec![
+                ModeCapability { mode: PinMode::DigitalInput as u8, resolution: 1 },
+                ModeCapability { mode: PinMode::AnalogInput as u8, resolution: 10 },
+            ],
+            analog_channel: Some(2),
+            value: 0,
+            mode: PinMode::AnalogInput as u8,
+        };
+
+        let pin = AnalogPin::from_analog(2, 16, &firmata_pin).unwrap();
+
+        assert_eq!(pin.channel(), 2);
+        assert_eq!(pin.bit_resolution(), 10);
+    }
+
+    #[test]
+    fn unknown_pin_mode() {
+        let err = PinMode::try_from(0x7E).unwrap_err();
+
+        assert_eq!(err, Error::UnknownPinMode(0x7E));
+    }
+
+    #[test]
+    fn digital_pin_with_unknown_mode() {
+        let firmata_pin = FirmataPin {
+            modes: vec![ModeCapability { mode: 0x7E, resolution: 1 }],
+            analog_channel: None,
+            value: 0,
+            mode: 0x7E,
+        };
+
+        let err = DigitalPin::from_digital(&firmata_pin).unwrap_err();
+
+        assert_eq!(err, Error::UnknownPinMode(0x7E));
+    }
 }
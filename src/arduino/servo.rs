@@ -0,0 +1,67 @@
+use std::ops::RangeInclusive;
+
+use crate::arduino::{Arduino, Error};
+
+/// The largest angle a servo can be set to.
+const MAX_ANGLE: u8 = 180;
+
+/// The Arduino's servo library interprets values below this pulse width (in microseconds) as
+/// angles.
+const MIN_PULSE_WIDTH: u16 = 544;
+
+/// The pulse width bounds (in microseconds) with which a servo was attached.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct ServoConfig {
+    pub(crate) min_pulse: u16,
+    pub(crate) max_pulse: u16,
+}
+
+/// A handle on a servo attached to a pin of an Arduino.
+///
+/// You can get hold of servo instances by calling `Arduino::attach_servo` or `Arduino::servo`.
+pub struct Servo<'a> {
+    arduino: &'a mut Arduino,
+    pin_index: i32,
+    config: ServoConfig,
+}
+
+impl<'a> Servo<'a> {
+
+    pub(crate) fn new(arduino: &'a mut Arduino, pin_index: i32, config: ServoConfig) -> Servo<'a> {
+        Servo { arduino, pin_index, config }
+    }
+
+    /// The index of the pin the servo is attached to.
+    pub fn pin_index(&self) -> i32 { self.pin_index }
+
+    /// The range of pulse widths (in microseconds) the servo was attached with.
+    pub fn pulse_widths(&self) -> RangeInclusive<u16> {
+        self.config.min_pulse..=self.config.max_pulse
+    }
+
+    /// Turns the servo to a given angle, which is mapped onto the servo's pulse width range by the
+    /// Arduino.
+    ///
+    /// # Errors
+    /// * `ValueOutOfBounds`, if the angle is greater than 180 degrees.
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn set_angle(&mut self, degrees: u8) -> Result<(), Error> {
+        if degrees > MAX_ANGLE { return Err(Error::ValueOutOfBounds); }
+
+        self.arduino.servo_write(self.pin_index, degrees.into())
+    }
+
+    /// Sets the width of the pulses sent to the servo.
+    ///
+    /// # Errors
+    /// * `ValueOutOfBounds`, if the pulse width lies outside the servo's pulse width range, or
+    ///   below 544 microseconds (as those are interpreted as angles by the Arduino).
+    /// * `Disconnected`, if the connection to the Arduino was lost.
+    pub fn set_pulse_width(&mut self, microseconds: u16) -> Result<(), Error> {
+        if !self.pulse_widths().contains(&microseconds) || microseconds < MIN_PULSE_WIDTH {
+            return Err(Error::ValueOutOfBounds);
+        }
+
+        self.arduino.servo_write(self.pin_index, microseconds.into())
+    }
+}
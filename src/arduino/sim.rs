@@ -0,0 +1,652 @@
+//! This module provides a virtual Arduino running StandardFirmata, which an `Arduino` can connect
+//! to without any hardware being present (e.g. for testing).
+//!
+//! A `VirtualBoard` answers the host's queries according to its `Layout`, tracks the modes and
+//! values written to its pins, and reports input values which are set directly or by a script.
+
+use std::io::{self, Read, Write};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, mpsc};
+use std::thread;
+use std::time::Duration;
+use std::convert::TryFrom;
+
+use crate::arduino::{PinMode, I2cReply};
+use crate::arduino::Transport;
+use crate::arduino::transport::ChannelReader;
+use crate::arduino::event::PINS_PER_PORT;
+use crate::arduino::firmata::{Message, Parser, Origin, ModeCapability, I2cMode};
+
+/// The Firmata protocol version reported by a virtual board, unless configured otherwise.
+const PROTOCOL_VERSION: (u8, u8) = (2, 5);
+
+/// The firmware reported by a virtual board, unless configured otherwise.
+const FIRMWARE: (u8, u8, &str) = (2, 5, "StandardFirmata.ino");
+
+/// StandardFirmata replies with this register, when reading without specifying a register.
+const UNSPECIFIED_I2C_REGISTER: u16 = 0xFF;
+
+/// The pins of a virtual board, i.e. the modes supported by each pin and the analog channel of
+/// each analog pin.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Layout {
+    pins: Vec<Vec<ModeCapability>>,
+    analog_mapping: Vec<Option<u8>>,
+}
+
+impl Layout {
+
+    /// Creates a layout with the given supported modes for each pin, and the given analog channel
+    /// of each pin (or `None` for non-analog pins).
+    ///
+    /// # Panics
+    /// * if the number of pins in the capabilities and the analog mapping differ.
+    pub fn new(pins: Vec<Vec<ModeCapability>>, analog_mapping: Vec<Option<u8>>) -> Layout {
+        assert_eq!(pins.len(), analog_mapping.len(), "Capabilities and analog mapping differ in length.");
+        Layout { pins, analog_mapping }
+    }
+
+    /// The layout of an Arduino Uno, with digital pins 0 to 13 and analog pins A0 to A5.
+    pub fn uno() -> Layout {
+        Layout::standard(14, &[3, 5, 6, 9, 10, 11], 6, &[18, 19], 0)
+    }
+
+    /// The layout of an Arduino Nano, which extends the Uno's layout with the analog-only pins A6
+    /// and A7.
+    pub fn nano() -> Layout {
+        Layout::standard(14, &[3, 5, 6, 9, 10, 11], 6, &[18, 19], 2)
+    }
+
+    /// The layout of an Arduino Mega 2560, with digital pins 0 to 53 and analog pins A0 to A15.
+    pub fn mega() -> Layout {
+        let pwm_pins: Vec<u8> = (2..=13).chain(44..=46).collect();
+        Layout::standard(54, &pwm_pins, 16, &[20, 21], 0)
+    }
+
+    /// The number of pins in the layout.
+    pub fn pin_count(&self) -> usize { self.pins.len() }
+
+    /// The modes supported by each pin.
+    pub fn capabilities(&self) -> &[Vec<ModeCapability>] { &self.pins }
+
+    /// The analog channel of each pin, or `None` for non-analog pins.
+    pub fn analog_mapping(&self) -> &[Option<u8>] { &self.analog_mapping }
+
+    /// The layout of a board as reported by StandardFirmata, with the given number of digital pins
+    /// followed by the given number of analog pins, which can also be used digitally, and a given
+    /// number of analog-only pins.
+    fn standard(
+        digital_count: u8, pwm_pins: &[u8], analog_count: u8, i2c_pins: &[u8], analog_only_count: u8
+    ) -> Layout {
+        let mut pins = vec![];
+        let mut analog_mapping = vec![];
+
+        let capability = |mode: PinMode, resolution| ModeCapability { mode: mode as u8, resolution };
+
+        for pin in 0..(digital_count + analog_count) {
+            let mut modes = vec![
+                capability(PinMode::DigitalInput, 1),
+                capability(PinMode::DigitalOutput, 1),
+                capability(PinMode::InputPullup, 1),
+            ];
+
+            if pin >= digital_count { modes.push(capability(PinMode::AnalogInput, 10)); }
+            if pwm_pins.contains(&pin) { modes.push(capability(PinMode::Pwm, 8)); }
+
+            // The serial pins 0 and 1 can not be used for servos.
+            if pin >= 2 && pin < digital_count { modes.push(capability(PinMode::Servo, 14)); }
+            if i2c_pins.contains(&pin) { modes.push(capability(PinMode::I2c, 1)); }
+
+            pins.push(modes);
+            analog_mapping.push(pin.checked_sub(digital_count));
+        }
+
+        for channel in analog_count..(analog_count + analog_only_count) {
+            pins.push(vec![capability(PinMode::AnalogInput, 10)]);
+            analog_mapping.push(Some(channel));
+        }
+
+        Layout { pins, analog_mapping }
+    }
+}
+
+/// An input to a virtual board, as would be produced by its surroundings.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Input {
+    /// A digital level applied to the pin with the given index.
+    Digital { pin: u8, value: bool },
+    /// An analog value applied to the pin with the given analog channel.
+    Analog { channel: u8, value: u16 },
+}
+
+/// The state of a virtual board, as changed by the host and its inputs.
+struct Device {
+    layout: Layout,
+    protocol_version: (u8, u8),
+    firmware: (u8, u8, String),
+    modes: Vec<u8>,
+    values: Vec<i32>,
+    reporting_ports: HashSet<u8>,
+    reporting_channels: HashSet<u8>,
+    i2c_registers: HashMap<(u16, u16), Vec<u8>>,
+    i2c_reply_limits: HashMap<u16, usize>,
+    received: Vec<Message>,
+    sender: Option<mpsc::Sender<Vec<u8>>>,
+}
+
+impl Device {
+
+    fn new(layout: Layout) -> Device {
+        let mut device = Device {
+            modes: vec![], values: vec![],
+            reporting_ports: HashSet::new(),
+            reporting_channels: HashSet::new(),
+            i2c_registers: HashMap::new(),
+            i2c_reply_limits: HashMap::new(),
+            received: vec![],
+            sender: None,
+            layout,
+            protocol_version: PROTOCOL_VERSION,
+            firmware: (FIRMWARE.0, FIRMWARE.1, String::from(FIRMWARE.2)),
+        };
+
+        device.reset();
+        device
+    }
+
+    /// Resets the pins to the state StandardFirmata puts them in on startup, i.e. analog pins in
+    /// `AnalogInput` mode and all other pins in `DigitalOutput` mode.
+    fn reset(&mut self) {
+        self.modes = self.layout.analog_mapping.iter()
+            .map(|channel| if channel.is_some() { PinMode::AnalogInput } else { PinMode::DigitalOutput } as u8)
+            .collect();
+        self.values = vec![0; self.layout.pin_count()];
+        self.reporting_ports.clear();
+        self.reporting_channels.clear();
+    }
+
+    /// Sends a given message to the host, if it is connected.
+    fn send(&mut self, message: Message) {
+        let is_connected = self.sender.as_ref()
+            .is_some_and(|sender| sender.send(message.encode()).is_ok());
+
+        if !is_connected { self.sender = None; }
+    }
+
+    fn supports(&self, pin: u8, mode: u8) -> bool {
+        self.layout.pins.get(pin as usize)
+            .is_some_and(|modes| modes.iter().any(|capability| capability.mode == mode))
+    }
+
+    fn has_mode(&self, pin: usize, modes: &[PinMode]) -> bool {
+        self.modes.get(pin).is_some_and(|&mode| modes.iter().any(|&candidate| candidate as u8 == mode))
+    }
+
+    fn pin_for_channel(&self, channel: u8) -> Option<usize> {
+        self.layout.analog_mapping.iter().position(|&candidate| candidate == Some(channel))
+    }
+
+    /// The value of a given digital port, as reported by StandardFirmata, which only includes the
+    /// values of pins in an input mode.
+    fn port_value(&self, port: u8) -> u16 {
+        (0..PINS_PER_PORT)
+            .map(|bit| (bit, port as usize * PINS_PER_PORT + bit))
+            .filter(|&(_, pin)| self.has_mode(pin, &[PinMode::DigitalInput, PinMode::InputPullup]))
+            .filter(|&(_, pin)| self.values[pin] != 0)
+            .fold(0, |value, (bit, _)| value | (1 << bit))
+    }
+
+    fn report_port(&mut self, port: u8) {
+        if self.reporting_ports.contains(&port) {
+            let value = self.port_value(port);
+            self.send(Message::DigitalPort { port, value });
+        }
+    }
+
+    fn report_channel(&mut self, channel: u8) {
+        if !self.reporting_channels.contains(&channel) { return; }
+
+        if let Some(pin) = self.pin_for_channel(channel) {
+            if self.has_mode(pin, &[PinMode::AnalogInput]) {
+                let value = self.values[pin] as u16;
+                self.send(Message::Analog { pin: channel, value });
+            }
+        }
+    }
+
+    /// Applies an input from the board's surroundings, reporting it to the host if necessary.
+    fn apply(&mut self, input: Input) {
+        match input {
+            Input::Digital { pin, value } => {
+                if pin as usize >= self.values.len() { return; }
+
+                let port = (pin as usize / PINS_PER_PORT) as u8;
+                let previous = self.port_value(port);
+                self.values[pin as usize] = value.into();
+
+                if self.port_value(port) != previous { self.report_port(port); }
+            },
+            Input::Analog { channel, value } => {
+                if let Some(pin) = self.pin_for_channel(channel) {
+                    self.values[pin] = value.into();
+                    self.report_channel(channel);
+                }
+            },
+        }
+    }
+
+    /// Handles a given message sent by the host, as StandardFirmata would.
+    fn handle(&mut self, message: Message) {
+        self.received.push(message.clone());
+
+        match message {
+            Message::CapabilityQuery => self.send(Message::Capabilities(self.layout.pins.clone())),
+            Message::AnalogMappingQuery => self.send(Message::AnalogMapping(self.layout.analog_mapping.clone())),
+            Message::ProtocolVersionQuery => {
+                let (major, minor) = self.protocol_version;
+                self.send(Message::ProtocolVersion { major, minor });
+            },
+            Message::FirmwareQuery => {
+                let (major, minor, name) = self.firmware.clone();
+                self.send(Message::Firmware { major, minor, name });
+            },
+            Message::SystemReset => self.reset(),
+            Message::SetPinMode { pin, mode } => {
+                if self.supports(pin, mode) {
+                    self.modes[pin as usize] = mode;
+                } else {
+                    self.send(Message::StringData(String::from("Unknown pin mode")));
+                }
+            },
+            Message::DigitalPort { port, value } => {
+                for bit in 0..PINS_PER_PORT {
+                    let pin = port as usize * PINS_PER_PORT + bit;
+
+                    if self.has_mode(pin, &[PinMode::DigitalOutput]) {
+                        self.values[pin] = ((value >> bit) & 1).into();
+                    }
+                }
+            },
+            Message::SetDigitalPinValue { pin, value } if self.has_mode(pin as usize, &[PinMode::DigitalOutput]) => {
+                self.values[pin as usize] = value.into();
+            },
+            Message::Analog { pin, value } => self.analog_write(pin, value.into()),
+            Message::ExtendedAnalog { pin, value } => self.analog_write(pin, value as i32),
+            Message::ReportDigital { port, enabled } => {
+                if enabled {
+                    self.reporting_ports.insert(port);
+                    self.report_port(port);
+                } else {
+                    self.reporting_ports.remove(&port);
+                }
+            },
+            Message::ReportAnalog { channel, enabled } => {
+                if enabled {
+                    self.reporting_channels.insert(channel);
+                    self.report_channel(channel);
+                } else {
+                    self.reporting_channels.remove(&channel);
+                }
+            },
+            Message::PinStateQuery { pin } => {
+                if let (Some(&mode), Some(&value)) = (self.modes.get(pin as usize), self.values.get(pin as usize)) {
+                    self.send(Message::PinState { pin, mode, state: value as u32 });
+                }
+            },
+            Message::I2cRequest { address, mode, data } => self.i2c_request(address, mode, &data),
+            _ => {},
+        }
+    }
+
+    fn analog_write(&mut self, pin: u8, value: i32) {
+        if self.has_mode(pin as usize, &[PinMode::Pwm, PinMode::Servo]) {
+            self.values[pin as usize] = value;
+        }
+    }
+
+    /// Writes to or reads from a device's registers, which are bytes sent by the host. Writes
+    /// store all bytes after the first at the register given by the first byte.
+    fn i2c_request(&mut self, address: u16, mode: I2cMode, data: &[u16]) {
+        match mode {
+            I2cMode::Write => {
+                if let Some((&register, bytes)) = data.split_first() {
+                    let bytes = bytes.iter().map(|&byte| byte as u8).collect();
+                    self.i2c_registers.insert((address, register), bytes);
+                }
+            },
+            I2cMode::Read | I2cMode::ReadContinuously => {
+                let (register, len) = match *data {
+                    [register, len] => (register, len),
+                    [len] => (UNSPECIFIED_I2C_REGISTER, len),
+                    _ => return,
+                };
+
+                let mut bytes = self.i2c_registers.get(&(address, register)).cloned().unwrap_or_default();
+                bytes.resize(len as usize, 0);
+
+                if let Some(&limit) = self.i2c_reply_limits.get(&address) { bytes.truncate(limit); }
+
+                self.send(Message::I2cReply(I2cReply { address, register, data: bytes }));
+            },
+            I2cMode::StopReading => {},
+        }
+    }
+}
+
+/// A virtual Arduino running StandardFirmata.
+///
+/// The board is shared with the transports created via `transport`, so it can be inspected and
+/// fed with inputs while an `Arduino` is connected to it.
+#[derive(Clone)]
+pub struct VirtualBoard {
+    device: Arc<Mutex<Device>>,
+}
+
+impl VirtualBoard {
+
+    /// Creates a virtual board with the given layout.
+    pub fn new(layout: Layout) -> VirtualBoard {
+        VirtualBoard { device: Arc::new(Mutex::new(Device::new(layout))) }
+    }
+
+    /// Creates an in-memory transport over which the board can be reached.
+    /// Only the most recently created transport is connected to the board.
+    pub fn transport(&self) -> VirtualTransport {
+        let (sender, receiver) = mpsc::channel();
+        self.lock().sender = Some(sender);
+
+        VirtualTransport { device: Arc::clone(&self.device), receiver }
+    }
+
+    /// Disconnects the board from its transport, as if its cable was unplugged.
+    pub fn disconnect(&self) {
+        self.lock().sender = None;
+    }
+
+    /// Sets the firmware name and version, and the Firmata protocol version reported by the board.
+    /// By default, the board reports StandardFirmata 2.5 implementing protocol version 2.5.
+    pub fn set_firmware(&self, name: &str, version: (u8, u8), protocol_version: (u8, u8)) {
+        let mut device = self.lock();
+        device.firmware = (version.0, version.1, String::from(name));
+        device.protocol_version = protocol_version;
+    }
+
+    /// Resets the board, as if its reset button was pressed. This puts all pins back into the state
+    /// they were in on startup.
+    pub fn reset(&self) {
+        self.lock().reset();
+    }
+
+    /// Applies a given input to the board, which is reported to the host if it is interested in
+    /// the input's pin.
+    pub fn apply(&self, input: Input) {
+        self.lock().apply(input);
+    }
+
+    /// Applies the inputs of a given script on a background thread, waiting the given duration
+    /// before each input.
+    pub fn play<S>(&self, script: S) -> thread::JoinHandle<()>
+    where S: IntoIterator<Item = (Duration, Input)> + Send + 'static {
+        let board = self.clone();
+
+        thread::spawn(move || {
+            for (delay, input) in script {
+                thread::sleep(delay);
+                board.apply(input);
+            }
+        })
+    }
+
+    /// Stores the given bytes in a register of the I2C device with a given address, from where
+    /// they can be read by the host.
+    pub fn set_i2c_register(&self, address: u16, register: u16, bytes: &[u8]) {
+        self.lock().i2c_registers.insert((address, register), bytes.to_vec());
+    }
+
+    /// Limits the number of bytes the I2C device with a given address replies with. StandardFirmata
+    /// replies with fewer bytes than requested, if the device does not provide enough of them.
+    pub fn set_i2c_reply_limit(&self, address: u16, limit: usize) {
+        self.lock().i2c_reply_limits.insert(address, limit);
+    }
+
+    /// The bytes last written to a register of the I2C device with a given address.
+    pub fn i2c_register(&self, address: u16, register: u16) -> Option<Vec<u8>> {
+        self.lock().i2c_registers.get(&(address, register)).cloned()
+    }
+
+    /// The current mode of the pin with a given index.
+    pub fn pin_mode(&self, pin: u8) -> Option<PinMode> {
+        let mode = *self.lock().modes.get(pin as usize)?;
+        PinMode::try_from(mode).ok()
+    }
+
+    /// The current value of the pin with a given index, i.e. the value last written by the host
+    /// for output pins, or the value last applied as an input for input pins.
+    pub fn pin_value(&self, pin: u8) -> Option<i32> {
+        self.lock().values.get(pin as usize).cloned()
+    }
+
+    /// All messages received from the host so far.
+    pub fn received(&self) -> Vec<Message> {
+        self.lock().received.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Device> {
+        self.device.lock().expect("Virtual board mutex was poisoned.")
+    }
+}
+
+/// An in-memory transport to a `VirtualBoard`.
+pub struct VirtualTransport {
+    device: Arc<Mutex<Device>>,
+    receiver: mpsc::Receiver<Vec<u8>>,
+}
+
+impl Transport for VirtualTransport {
+
+    fn split(self) -> io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+        let reader = ChannelReader::new(self.receiver);
+        let writer = VirtualWriter { device: self.device, parser: Parser::new(Origin::Host) };
+
+        Ok((Box::new(reader), Box::new(writer)))
+    }
+}
+
+/// The writing half of a `VirtualTransport`, which passes the host's messages to the board.
+struct VirtualWriter {
+    device: Arc<Mutex<Device>>,
+    parser: Parser,
+}
+
+impl Write for VirtualWriter {
+
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let mut device = self.device.lock().expect("Virtual board mutex was poisoned.");
+        if device.sender.is_none() { return Err(io::ErrorKind::BrokenPipe.into()); }
+
+        for &byte in buffer {
+            if let Some(message) = self.parser.push(byte) { device.handle(message); }
+        }
+
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arduino::{Arduino, Error, Pin};
+
+    fn connect(layout: Layout) -> (VirtualBoard, Arduino) {
+        let board = VirtualBoard::new(layout);
+        let arduino = Arduino::connect_transport(board.transport(), Duration::from_secs(1)).unwrap();
+
+        (board, arduino)
+    }
+
+    #[test]
+    fn layouts() {
+        assert_eq!(Layout::uno().pin_count(), 20);
+        assert_eq!(Layout::nano().pin_count(), 22);
+        assert_eq!(Layout::mega().pin_count(), 70);
+    }
+
+    #[test]
+    fn connects_with_layout() {
+        let (_, arduino) = connect(Layout::mega());
+
+        assert_eq!(arduino.digital_pins().len(), 54);
+        assert_eq!(arduino.analog_pins().len(), 16);
+        assert_eq!(arduino.analog_pins()[15].channel(), 15);
+    }
+
+    #[test]
+    fn syncs_pin_states() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        assert_eq!(arduino.digital_pins()[13].mode(), PinMode::DigitalOutput);
+
+        arduino.set_pin_mode(9, PinMode::Pwm).unwrap();
+        arduino.write(9, 42).unwrap();
+
+        assert_eq!(arduino.digital_pins()[9].value(), 42);
+
+        // Resetting the board is only noticed when syncing.
+        board.reset();
+
+        assert_eq!(arduino.digital_pins()[9].mode(), PinMode::Pwm);
+
+        arduino.sync_pin_states().unwrap();
+        let pin = &arduino.digital_pins()[9];
+
+        assert_eq!((pin.mode(), pin.value()), (PinMode::DigitalOutput, 0));
+    }
+
+    #[test]
+    fn firmware() {
+        let board = VirtualBoard::new(Layout::uno());
+        board.set_firmware("CustomFirmata.ino", (1, 2), (2, 3));
+
+        let arduino = Arduino::connect_transport(board.transport(), Duration::from_secs(1)).unwrap();
+        let firmware = arduino.firmware();
+
+        assert_eq!(firmware.name(), "CustomFirmata.ino");
+        assert_eq!(firmware.version(), (1, 2));
+        assert_eq!(firmware.protocol_version(), (2, 3));
+    }
+
+    #[test]
+    fn digital_write() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        arduino.set_pin_mode(13, PinMode::DigitalOutput).unwrap();
+        arduino.write(13, 1).unwrap();
+
+        assert_eq!(board.pin_mode(13), Some(PinMode::DigitalOutput));
+        assert_eq!(board.pin_value(13), Some(1));
+
+        arduino.write(13, 0).unwrap();
+
+        assert_eq!(board.pin_value(13), Some(0));
+    }
+
+    #[test]
+    fn pwm_write() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        arduino.set_pin_mode(9, PinMode::Pwm).unwrap();
+        arduino.write(9, 128).unwrap();
+
+        assert_eq!(board.pin_mode(9), Some(PinMode::Pwm));
+        assert_eq!(board.pin_value(9), Some(128));
+    }
+
+    #[test]
+    fn servo_write() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        let mut servo = arduino.attach_servo(9, 600, 2400).unwrap();
+        servo.set_angle(90).unwrap();
+
+        assert_eq!(board.pin_mode(9), Some(PinMode::Servo));
+        assert_eq!(board.pin_value(9), Some(90));
+        assert_eq!(arduino.digital_pins()[9].value(), 90);
+
+        arduino.servo(9).unwrap().set_pulse_width(1500).unwrap();
+
+        assert_eq!(board.pin_value(9), Some(1500));
+        assert_eq!(arduino.digital_pins()[9].value(), 1500);
+    }
+
+    #[test]
+    fn unsupported_mode() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        assert_eq!(arduino.set_pin_mode(2, PinMode::Pwm), Err(Error::InvalidMode));
+        assert_eq!(board.pin_mode(2), Some(PinMode::DigitalOutput));
+    }
+
+    #[test]
+    fn digital_read() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        board.apply(Input::Digital { pin: 4, value: true });
+        arduino.set_pin_mode(4, PinMode::DigitalInput).unwrap();
+
+        assert_eq!(arduino.read_digital(4), Ok(1));
+    }
+
+    #[test]
+    fn analog_read() {
+        let (board, mut arduino) = connect(Layout::nano());
+
+        board.apply(Input::Analog { channel: 7, value: 512 });
+
+        assert_eq!(arduino.read_analog(7).unwrap().value(), 512);
+    }
+
+    #[test]
+    fn scripted_events() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        // The pin's initial value is reported when its mode changes.
+        let events = arduino.subscribe(&[Pin::Digital(2)]).unwrap();
+        arduino.set_pin_mode(2, PinMode::InputPullup).unwrap();
+
+        let step = Duration::from_millis(5);
+        let script = vec![
+            (step, Input::Digital { pin: 2, value: true }),
+            (step, Input::Digital { pin: 2, value: false }),
+        ];
+        board.play(script).join().unwrap();
+
+        let values: Vec<i32> = events.iter().take(3).map(|event| event.value()).collect();
+
+        assert_eq!(values, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn i2c_read() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        board.set_i2c_register(0x48, 2, &[0x12, 0x34]);
+        let reply = arduino.i2c_read(0x48, 2, 2).unwrap();
+
+        assert_eq!(reply.data(), &[0x12, 0x34]);
+
+        arduino.i2c_write(0x48, &[3, 0xAB]).unwrap();
+
+        assert_eq!(board.i2c_register(0x48, 3), Some(vec![0xAB]));
+    }
+
+    #[test]
+    fn disconnect() {
+        let (board, mut arduino) = connect(Layout::uno());
+
+        board.disconnect();
+
+        assert_eq!(arduino.set_pin_mode(13, PinMode::DigitalOutput), Err(Error::Disconnected));
+    }
+}
@@ -0,0 +1,286 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// The baud rate used by StandardFirmata.
+pub(crate) const BAUD_RATE: u32 = 57_600;
+
+/// The time after which reads time out, so that the background thread reading from a transport
+/// can notice when its Arduino is dropped.
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// A bidirectional byte stream over which an Arduino running Firmata can be reached.
+///
+/// As an Arduino's messages are read on a background thread, a transport is split into independent
+/// reading and writing halves. The reading half should not block indefinitely, as the background
+/// thread only stops after a read returns. If no bytes are available, it should fail with
+/// `WouldBlock` or `TimedOut` instead, as reading zero bytes means that the connection was closed.
+///
+/// Any other byte stream can be used as a transport via `StreamTransport`.
+pub trait Transport {
+
+    /// Splits the transport into a reading and a writing half.
+    fn split(self) -> io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)>;
+}
+
+/// A transport over a serial port, as used by Arduinos connected via USB.
+pub struct SerialTransport {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl SerialTransport {
+
+    /// Opens the serial port with the given name (e.g. `/dev/ttyACM0` or `COM3`), with the
+    /// settings expected by StandardFirmata.
+    pub fn open(port: &str) -> io::Result<SerialTransport> {
+        let port = serialport::new(port, BAUD_RATE)
+            .timeout(READ_TIMEOUT)
+            .open()?;
+
+        Ok(SerialTransport { port })
+    }
+}
+
+impl Transport for SerialTransport {
+
+    fn split(self) -> io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+        let reader = self.port.try_clone()?;
+        Ok((Box::new(reader), Box::new(self.port)))
+    }
+}
+
+/// A transport over TCP, as used by Arduinos running StandardFirmataWiFi or
+/// StandardFirmataEthernet.
+impl Transport for TcpStream {
+
+    fn split(self) -> io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+        let reader = self.try_clone()?;
+        reader.set_read_timeout(Some(READ_TIMEOUT))?;
+        self.set_nodelay(true)?;
+
+        Ok((Box::new(reader), Box::new(self)))
+    }
+}
+
+/// A transport over any bidirectional byte stream, e.g. a Unix socket or a Bluetooth serial port.
+///
+/// As a stream can not generally be split, both halves share the stream behind a lock. A read
+/// which blocks therefore also blocks writing, so reads should time out after a short while (e.g.
+/// via `UnixStream::set_read_timeout`) or fail with `WouldBlock`. Streams which can be cloned,
+/// like serial ports and TCP streams, are better served by `SerialTransport` and the transport
+/// implemented by `TcpStream`.
+pub struct StreamTransport<S> {
+    stream: S,
+}
+
+impl<S: Read + Write + Send + 'static> StreamTransport<S> {
+
+    pub fn new(stream: S) -> StreamTransport<S> {
+        StreamTransport { stream }
+    }
+}
+
+impl<S: Read + Write + Send + 'static> Transport for StreamTransport<S> {
+
+    fn split(self) -> io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+        let stream = Arc::new(Mutex::new(self.stream));
+        Ok((Box::new(SharedStream(Arc::clone(&stream))), Box::new(SharedStream(stream))))
+    }
+}
+
+/// A half of a `StreamTransport`, which locks the shared stream for every read and write.
+struct SharedStream<S>(Arc<Mutex<S>>);
+
+impl<S> SharedStream<S> {
+
+    fn lock(&self) -> MutexGuard<'_, S> {
+        self.0.lock().expect("Stream lock failed.")
+    }
+}
+
+impl<S: Read> Read for SharedStream<S> {
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.lock().read(buffer)
+    }
+}
+
+impl<S: Write> Write for SharedStream<S> {
+
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.lock().write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+/// A reading half of a transport, which receives the bytes sent over a channel (e.g. by another
+/// thread bridging to the actual device).
+pub(crate) struct ChannelReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl ChannelReader {
+
+    pub(crate) fn new(receiver: mpsc::Receiver<Vec<u8>>) -> ChannelReader {
+        ChannelReader { receiver, pending: vec![] }
+    }
+}
+
+impl Read for ChannelReader {
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        // Empty chunks are skipped, as reading zero bytes would indicate a closed connection.
+        while self.pending.is_empty() {
+            self.pending = match self.receiver.recv_timeout(READ_TIMEOUT) {
+                Ok(bytes) => bytes,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(io::ErrorKind::BrokenPipe.into()),
+            };
+        }
+
+        let count = buffer.len().min(self.pending.len());
+        buffer[..count].copy_from_slice(&self.pending[..count]);
+        self.pending.drain(..count);
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use crate::arduino::{Arduino, PinMode};
+    use crate::arduino::firmata::{Message, Parser, Origin, ModeCapability};
+
+    /// Accepts one connection on a local listener, answering the Firmata handshake queries as a
+    /// device with two digital pins (the second supporting PWM) and one analog pin.
+    /// The listener closes the connection once it receives a message for which the given function
+    /// returns `true`. All bytes received by the listener are returned once the connection is
+    /// closed.
+    fn firmata_listener(hangs_up: fn(&Message) -> bool) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut parser = Parser::new(Origin::Host);
+            let mut received = vec![];
+            let mut buffer = [0u8; 64];
+
+            'connection: while let Ok(count) = stream.read(&mut buffer) {
+                if count == 0 { break; }
+                received.extend_from_slice(&buffer[..count]);
+
+                for &byte in &buffer[..count] {
+                    let message = parser.push(byte);
+                    if message.as_ref().is_some_and(hangs_up) { break 'connection; }
+
+                    let response = match message {
+                        Some(Message::CapabilityQuery) => Message::Capabilities(vec![
+                            vec![capability(0, 1), capability(1, 1)],
+                            vec![capability(0, 1), capability(1, 1), capability(3, 8)],
+                            vec![capability(2, 10)],
+                        ]),
+                        Some(Message::AnalogMappingQuery) => Message::AnalogMapping(vec![None, None, Some(0)]),
+                        Some(Message::PinStateQuery { pin }) => Message::PinState { pin, mode: 1, state: 0 },
+                        Some(Message::ProtocolVersionQuery) => Message::ProtocolVersion { major: 2, minor: 5 },
+                        Some(Message::FirmwareQuery) => Message::Firmware {
+                            major: 2, minor: 5, name: String::from("StandardFirmataWiFi.ino"),
+                        },
+                        _ => continue,
+                    };
+
+                    stream.write_all(&response.encode()).unwrap();
+                }
+            }
+
+            received
+        });
+
+        (address, handle)
+    }
+
+    fn capability(mode: u8, resolution: u8) -> ModeCapability {
+        ModeCapability { mode, resolution }
+    }
+
+    fn contains(bytes: &[u8], message: &[u8]) -> bool {
+        bytes.windows(message.len()).any(|window| window == message)
+    }
+
+    #[test]
+    fn connects_over_tcp() {
+        let (address, listener) = firmata_listener(|_| false);
+        let stream = TcpStream::connect(address).unwrap();
+
+        let mut arduino = Arduino::connect_transport(stream, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(arduino.firmware().name(), "StandardFirmataWiFi.ino");
+        assert_eq!(arduino.digital_pins().len(), 2);
+        assert_eq!(arduino.analog_pins().len(), 1);
+
+        arduino.set_pin_mode(1, PinMode::Pwm).unwrap();
+        arduino.write(1, 200).unwrap();
+        drop(arduino);
+
+        let received = listener.join().unwrap();
+
+        assert!(contains(&received, &[0xF4, 0x01, 0x03]));
+        assert!(contains(&received, &[0xE1, 0x48, 0x01]));
+    }
+
+    #[test]
+    fn disconnects_when_closed_by_peer() {
+        let (address, listener) = firmata_listener(|message| matches!(message, Message::ReportAnalog { .. }));
+        let stream = TcpStream::connect(address).unwrap();
+        let mut arduino = Arduino::connect_transport(stream, Duration::from_secs(1)).unwrap();
+
+        let result = arduino.read_analog_with_timeout(0, Duration::from_secs(5));
+        listener.join().unwrap();
+
+        assert_eq!(result.err(), Some(crate::arduino::Error::Disconnected));
+    }
+
+    #[test]
+    fn connects_over_stream() {
+        let (address, listener) = firmata_listener(|_| false);
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+
+        let arduino = Arduino::connect_transport(StreamTransport::new(stream), Duration::from_secs(1)).unwrap();
+
+        assert_eq!(arduino.firmware().name(), "StandardFirmataWiFi.ino");
+        drop(arduino);
+        listener.join().unwrap();
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let result = Arduino::connect_transport(stream, Duration::from_millis(50));
+
+        assert_eq!(result.err(), Some(crate::arduino::Error::HandshakeTimeout));
+    }
+
+    #[test]
+    fn not_firmata() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut device, _) = listener.accept().unwrap();
+
+        device.write_all(b"Hello from a sketch!\r\n").unwrap();
+        let result = Arduino::connect_transport(stream, Duration::from_millis(100));
+
+        assert_eq!(result.err(), Some(crate::arduino::Error::NotFirmata));
+    }
+}
@@ -1,25 +1,156 @@
 use std::str;
-use std::process::Command;
+use std::collections::HashMap;
+use std::process::{Command, Output};
 use serde::{Serialize, Deserialize};
 use serde_json as json;
 
 use super::Error;
+use super::client::{ArduinoCli, default_client};
+use super::version::{Version, DETECTED_PORTS_VERSION, WRAPPED_OUTPUT_VERSION};
 
 /// A wrapper for the result of calling `arduino-cli board list --format json`, in order to take
 /// advantage of serde's derived JSON (de)serialization.
+///
+/// This is the format printed by versions of the Arduino CLI before 0.11, to which the formats of
+/// later versions are converted.
 #[derive(Serialize, Deserialize)]
 #[allow(non_snake_case)]
-struct BoardList {
-    serialBoards: Vec<Board>,
-    networkBoards: Vec<Board>,
+pub(super) struct BoardList {
+    pub(super) serialBoards: Vec<Board>,
+    pub(super) networkBoards: Vec<NetworkBoard>,
+}
+
+/// The result of calling `arduino-cli board list --format json` since version 0.36, which wraps
+/// the detected ports in an object.
+///
+/// Versions from 0.11 to 0.35 print a bare (possibly `null`) list of detected ports instead.
+#[derive(Deserialize)]
+struct DetectedPortList {
+    #[serde(default)]
+    detected_ports: Vec<DetectedPort>,
+}
+
+/// A port detected by the Arduino CLI, along with the boards which match it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DetectedPort {
+    /// Printed by versions since 0.19.
+    Nested {
+        port: PortInfo,
+        #[serde(default)]
+        matching_boards: Vec<MatchingBoard>,
+    },
+    /// Printed by versions from 0.11 to 0.18.
+    Flat {
+        #[serde(flatten)]
+        port: PortInfo,
+        #[serde(default)]
+        boards: Vec<MatchingBoard>,
+    },
+}
+
+/// The description of a detected port.
+#[derive(Deserialize)]
+struct PortInfo {
+    address: String,
+    protocol: String,
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+/// A board which matches a detected port.
+#[derive(Deserialize)]
+struct MatchingBoard {
+    name: String,
+    #[serde(alias = "FQBN", default)]
+    fqbn: String,
+    vid: Option<String>,
+    pid: Option<String>,
+}
+
+impl BoardList {
+
+    /// Converts the given detected ports into a board list.
+    fn from_detected_ports(ports: Vec<DetectedPort>) -> BoardList {
+        let mut board_list = BoardList { serialBoards: vec![], networkBoards: vec![] };
+
+        for detected_port in ports {
+            let (port, boards) = match detected_port {
+                DetectedPort::Nested { port, matching_boards } => (port, matching_boards),
+                DetectedPort::Flat { port, boards } => (port, boards),
+            };
+
+            // Only the first matching board is used, as a port can only be connected to one board.
+            let board = boards.into_iter().next();
+            let (name, fqbn) = match &board {
+                Some(board) => (board.name.clone(), board.fqbn.clone()),
+                None => (String::from(Board::UNKNOWN_CORE_NAME), String::from(Board::UNKNOWN_CORE_FQBN)),
+            };
+
+            // Ports of other protocols than serial and network (e.g. `dfu` for boards in bootloader
+            // mode) are not listed, as sketches can not be uploaded via the board list's targets.
+            if port.protocol == SERIAL_PROTOCOL {
+                let vid = board.as_ref().and_then(|board| board.vid.as_ref()).or_else(|| port.properties.get("vid"));
+                let pid = board.as_ref().and_then(|board| board.pid.as_ref()).or_else(|| port.properties.get("pid"));
+
+                // Serial ports without a board or USB ID are not connected to an Arduino (e.g.
+                // built-in serial ports), so they are not listed.
+                let usb_id = match (vid, pid) {
+                    (Some(vid), Some(pid)) => format!("{}:{}", strip_hex_prefix(vid), strip_hex_prefix(pid)),
+                    _ if board.is_some() => String::new(),
+                    _ => continue,
+                };
+
+                board_list.serialBoards.push(Board { name, fqbn, port: port.address, usbID: usb_id });
+            } else if port.protocol == NETWORK_PROTOCOL {
+                board_list.networkBoards.push(NetworkBoard {
+                    name, fqbn,
+                    port: port.properties.get("port").and_then(|port| port.parse().ok()),
+                    address: port.address,
+                    protocol: port.protocol,
+                });
+            }
+        }
+
+        board_list
+    }
 }
 
+/// Removes the `0x` prefix of a hexadecimal USB vendor or product ID, as printed by newer versions
+/// of the Arduino CLI.
+fn strip_hex_prefix(id: &str) -> &str {
+    id.strip_prefix("0x").unwrap_or(id)
+}
+
+/// A board which sketches can be compiled for and uploaded onto. This is implemented by serial
+/// and network boards alike.
+pub trait Target {
+
+    fn fqbn(&self) -> &str;
+
+    /// The address used for uploading, i.e. the serial port or network address of the board.
+    fn address(&self) -> &str;
+
+    /// The protocol used for uploading, e.g. `serial` or `network`.
+    fn protocol(&self) -> &str;
+
+    /// Indicates whether the board's core is not installed (or *was* not when the info was
+    /// captured).
+    fn has_unknown_core(&self) -> bool;
+}
+
+/// The protocol of boards connected via a serial port.
+const SERIAL_PROTOCOL: &str = "serial";
+
+/// The protocol of boards which are reachable over the network, unless reported otherwise.
+const NETWORK_PROTOCOL: &str = "network";
+
 /// A container for a line in the output produced by `arduino-cli board list`.
 ///
 /// A board may have placeholder values for the `name` and `fqbn` properties, if its core is not
 /// installed. This can be checked via the `has_unknown_core` method.
 ///
-/// You can get hold of board instances by calling `cli::board_list_serial`.
+/// You can get hold of board instances by calling `cli::board_list_serial` or `cli::board_list`.
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 #[allow(non_snake_case)]
 pub struct Board {
@@ -47,55 +178,245 @@ impl Board {
 
     pub fn fqbn(&self) -> &str { &self.fqbn }
 
+    /// The ID of the board's core (e.g. `arduino:avr` for the FQBN `arduino:avr:uno`), or `None`
+    /// if the core is unknown.
+    pub fn core_id(&self) -> Option<&str> {
+        if self.has_unknown_core() { return None; }
+
+        let mut components = self.fqbn.splitn(3, ':');
+        let vendor = components.next()?;
+        let architecture = components.next()?;
+
+        Some(&self.fqbn[..vendor.len() + 1 + architecture.len()])
+    }
+
     pub fn port(&self) -> &str { &self.port }
 
     pub fn id(&self) -> &str { &self.usbID }
 }
 
+impl Target for Board {
+
+    fn fqbn(&self) -> &str { &self.fqbn }
+
+    fn address(&self) -> &str { &self.port }
+
+    fn protocol(&self) -> &str { SERIAL_PROTOCOL }
+
+    fn has_unknown_core(&self) -> bool { Board::has_unknown_core(self) }
+}
+
+/// A board which is reachable over the network, e.g. an ESP- or Yún-class board, as listed by
+/// `arduino-cli board list`.
+///
+/// Sketches can be uploaded onto a network board over the air, but it can not be connected to via
+/// this library's `Arduino` type.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct NetworkBoard {
+    name: String,
+    fqbn: String,
+    address: String,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default = "NetworkBoard::default_protocol")]
+    protocol: String,
+}
+
+impl NetworkBoard {
+
+    fn default_protocol() -> String { String::from(NETWORK_PROTOCOL) }
+
+    pub fn board_name(&self) -> &str { &self.name }
+
+    pub fn fqbn(&self) -> &str { &self.fqbn }
+
+    /// The board's host name or IP address.
+    pub fn address(&self) -> &str { &self.address }
+
+    /// The network port on which the board accepts uploads, if it was announced.
+    pub fn port(&self) -> Option<u16> { self.port }
+
+    /// The protocol used for uploading onto the board, e.g. `network`.
+    pub fn protocol(&self) -> &str { &self.protocol }
+
+    /// Indicates whether the board's core is not installed (or *was* not when the info was
+    /// captured).
+    pub fn has_unknown_core(&self) -> bool { self.fqbn == Board::UNKNOWN_CORE_FQBN }
+}
+
+impl Target for NetworkBoard {
+
+    fn fqbn(&self) -> &str { &self.fqbn }
+
+    fn address(&self) -> &str { &self.address }
+
+    fn protocol(&self) -> &str { &self.protocol }
+
+    fn has_unknown_core(&self) -> bool { NetworkBoard::has_unknown_core(self) }
+}
+
+/// A board as listed by `cli::board_list`, which is either connected via a serial port or
+/// reachable over the network.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ListedBoard {
+    Serial(Board),
+    Network(NetworkBoard),
+}
+
+impl ListedBoard {
+
+    pub fn board_name(&self) -> &str {
+        match self {
+            ListedBoard::Serial(board) => board.board_name(),
+            ListedBoard::Network(board) => board.board_name(),
+        }
+    }
+
+    /// The board, if it is a serial board.
+    pub fn serial(self) -> Option<Board> {
+        match self {
+            ListedBoard::Serial(board) => Some(board),
+            ListedBoard::Network(_) => None,
+        }
+    }
+
+    /// The board, if it is a network board.
+    pub fn network(self) -> Option<NetworkBoard> {
+        match self {
+            ListedBoard::Serial(_) => None,
+            ListedBoard::Network(board) => Some(board),
+        }
+    }
+
+    /// The listed board as a target, which is used to implement `Target` by delegation.
+    fn target(&self) -> &dyn Target {
+        match self {
+            ListedBoard::Serial(board) => board,
+            ListedBoard::Network(board) => board,
+        }
+    }
+}
+
+impl Target for ListedBoard {
+
+    fn fqbn(&self) -> &str { self.target().fqbn() }
+
+    fn address(&self) -> &str { self.target().address() }
+
+    fn protocol(&self) -> &str { self.target().protocol() }
+
+    fn has_unknown_core(&self) -> bool { self.target().has_unknown_core() }
+}
+
 /// Calls `arduino-cli board list` and converts the resulting entries for serial boards into
 /// `Board` instances.
 /// Network boards are not returned, as they couldn't be connected to using this library's
-/// `Arduino` type.
+/// `Arduino` type. They can be listed via `cli::board_list_network` or `cli::board_list`.
 ///
 /// # Errors
-/// * `CommandFailure`, if the `arduino-cli` command fails or produces non-UTF-8 output.
+/// * `SpawnFailure` or `CommandFailure`, if the `arduino-cli` command can not be run or fails.
 /// * `UnknownFormat`, if the call to the Arduino CLI produced an output in a different format
 ///   than expected.
 pub fn board_list_serial() -> Result<Vec<Board>, Error> {
-    // Asks the Arduino CLI for a list of connected Arduinos in JSON format.
-    let command_stdout = Command::new("arduino-cli")
-        .args(&["board", "list", "--format", "json"])
-        .output()
-        .map(|output| output.stdout);
-
-    if let Ok(stdout) = command_stdout {
-        // The command line output has to be converted to a valid UTF-8 string before being able to
-        // use it.
-        str::from_utf8(&stdout)
-            .map_err(|_| Error::CommandFailure)
-            .and_then(boards_from_json)
-    } else {
-        Err(Error::CommandFailure)
+    default_client().board_list_serial()
+}
+
+/// Calls `arduino-cli board list` and converts the resulting entries for network boards into
+/// `NetworkBoard` instances.
+///
+/// # Errors
+/// * as described for `cli::board_list_serial`.
+pub fn board_list_network() -> Result<Vec<NetworkBoard>, Error> {
+    default_client().board_list_network()
+}
+
+/// Calls `arduino-cli board list` and converts all resulting entries into `ListedBoard`
+/// instances, with the serial boards preceding the network boards.
+///
+/// # Errors
+/// * as described for `cli::board_list_serial`.
+pub fn board_list() -> Result<Vec<ListedBoard>, Error> {
+    default_client().board_list()
+}
+
+impl ArduinoCli {
+
+    /// Lists the connected serial boards, as with `cli::board_list_serial`.
+    pub fn board_list_serial(&self) -> Result<Vec<Board>, Error> {
+        self.board_list().map(|boards| boards.into_iter().filter_map(ListedBoard::serial).collect())
+    }
+
+    /// Lists the network boards, as with `cli::board_list_network`.
+    pub fn board_list_network(&self) -> Result<Vec<NetworkBoard>, Error> {
+        self.board_list().map(|boards| boards.into_iter().filter_map(ListedBoard::network).collect())
+    }
+
+    /// Lists the serial and network boards, as with `cli::board_list`.
+    pub fn board_list(&self) -> Result<Vec<ListedBoard>, Error> {
+        let version = self.detected_version()?;
+        board_list_from_output(self.run(board_list_command(self)), version).map(listed_boards)
     }
 }
 
-/// Converts the serial boards in a given output from `arduino-cli board list --format json` into
-/// board instances.
+/// The command which asks the Arduino CLI for a list of connected Arduinos in JSON format.
+pub(super) fn board_list_command(cli: &ArduinoCli) -> Command {
+    cli.command(["board", "list", "--format", "json"])
+}
+
+/// Converts a board list into listed boards.
+pub(super) fn listed_boards(board_list: BoardList) -> Vec<ListedBoard> {
+    let serial = board_list.serialBoards.into_iter().map(ListedBoard::Serial);
+    let network = board_list.networkBoards.into_iter().map(ListedBoard::Network);
+
+    serial.chain(network).collect()
+}
+
+/// Converts the output of the board list command into a board list.
+pub(super) fn board_list_from_output(output: Result<Output, Error>, version: Version) -> Result<BoardList, Error> {
+    // The command line output has to be converted to a valid UTF-8 string before being able to
+    // use it.
+    str::from_utf8(&output?.stdout)
+        .map_err(|_| Error::UnknownFormat)
+        .and_then(|board_json| board_list_from_json(board_json, version))
+}
+
+/// Converts a given output from `arduino-cli board list --format json` into a board list, using
+/// the format printed by the given version of the Arduino CLI.
+///
+/// If the output does not have that format (e.g. because it changed in an unexpected version), the
+/// other formats are tried. As any object is read as an empty list of wrapped ports, such a list is
+/// only accepted if no other format matches.
 ///
 /// # Errors
 /// * `UnknownFormat`, if deserialization is unsuccessful.
-fn boards_from_json(board_json: &str) -> Result<Vec<Board>, Error> {
-    // Deserialization is handeled automatically by `Board`'s derived conformance to serde's
-    // `Deserialize`.
-    json::from_str(board_json)
-        .map(|board_list: BoardList| board_list.serialBoards)
-        .map_err(|_| Error::UnknownFormat)
+fn board_list_from_json(board_json: &str, version: Version) -> Result<BoardList, Error> {
+    // Deserialization is handeled automatically by the derived conformances to serde's
+    // `Deserialize`, after which all formats are converted to the legacy one.
+    let legacy = || json::from_str::<BoardList>(board_json).ok();
+    let bare = || json::from_str(board_json).ok()
+        .map(|ports: Option<Vec<DetectedPort>>| BoardList::from_detected_ports(ports.unwrap_or_default()));
+    let wrapped = |allows_empty: bool| json::from_str(board_json).ok()
+        .filter(|list: &DetectedPortList| allows_empty || !list.detected_ports.is_empty())
+        .map(|list| BoardList::from_detected_ports(list.detected_ports));
+
+    let board_list = if version < DETECTED_PORTS_VERSION {
+        legacy().or_else(bare).or_else(|| wrapped(false))
+    } else if version < WRAPPED_OUTPUT_VERSION {
+        bare().or_else(|| wrapped(false)).or_else(legacy)
+    } else {
+        wrapped(false).or_else(legacy).or_else(bare).or_else(|| wrapped(true))
+    };
+
+    board_list.ok_or(Error::UnknownFormat)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A version of the Arduino CLI which prints serial and network boards separately.
+    const LEGACY: Version = Version::new(0, 3, 6);
+
     /// A board with an installed core.
     fn some_board() -> Board {
         Board {
@@ -116,19 +437,32 @@ mod tests {
 
     /// A convenience function for creating the JSON-string, as would be printed by `arduino-cli
     /// board list --format json`, for a given list of boards.
-    fn json_for_boards(boards: &Vec<Board>) -> String {
-        // Network boards are currently not taken into account.
+    fn json_for_boards(boards: &[Board]) -> String {
         let board_list = BoardList {
-            serialBoards: boards.clone(),
+            serialBoards: boards.to_vec(),
             networkBoards: vec![],
         };
 
-        String::from(json::json!(board_list).to_string())
+        json::json!(board_list).to_string()
+    }
+
+    /// The serial boards in a given output from `arduino-cli board list --format json`.
+    fn boards_from_json(board_json: &str) -> Result<Vec<Board>, Error> {
+        board_list_from_json(board_json, LEGACY).map(|board_list| board_list.serialBoards)
+    }
+
+    /// A network board with an installed core.
+    fn network_board() -> NetworkBoard {
+        NetworkBoard {
+            name: String::from("ESP32 Dev Module"), fqbn: String::from("esp32:esp32:esp32"),
+            address: String::from("192.168.1.17"), port: Some(3232),
+            protocol: String::from(NETWORK_PROTOCOL),
+        }
     }
 
     #[test]
     fn no_boards() {
-        let no_board_json = &json_for_boards(&vec![]);
+        let no_board_json = &json_for_boards(&[]);
 
         let result = boards_from_json(no_board_json).unwrap();
 
@@ -155,6 +489,125 @@ mod tests {
         assert_eq!(result, boards);
     }
 
+    #[test]
+    fn network_boards() {
+        let board_json = r#"{
+            "serialBoards": [{"name": "A", "fqbn": "B", "port": "C", "usbID": "D"}],
+            "networkBoards": [{"name": "ESP32 Dev Module", "fqbn": "esp32:esp32:esp32", "address": "192.168.1.17", "port": 3232}]
+        }"#;
+
+        let result = listed_boards(board_list_from_json(board_json, LEGACY).unwrap());
+
+        assert_eq!(result, vec![ListedBoard::Serial(some_board()), ListedBoard::Network(network_board())]);
+    }
+
+    /// The Arduino Uno which is connected in all fixtures, with the given USB ID.
+    fn fixture_uno(name: &str, usb_id: &str) -> Board {
+        Board {
+            name: String::from(name), fqbn: String::from("arduino:avr:uno"),
+            port: String::from("/dev/ttyACM0"), usbID: String::from(usb_id),
+        }
+    }
+
+    /// A board connected via a USB to serial converter, whose core is unknown.
+    fn fixture_converter(usb_id: &str) -> Board {
+        Board {
+            name: String::from(Board::UNKNOWN_CORE_NAME), fqbn: String::from(Board::UNKNOWN_CORE_FQBN),
+            port: String::from("/dev/ttyUSB0"), usbID: String::from(usb_id),
+        }
+    }
+
+    #[test]
+    fn fixture_0_3() {
+        let board_list = board_list_from_json(include_str!("fixtures/board_list_0.3.json"), Version::new(0, 3, 6)).unwrap();
+
+        assert_eq!(board_list.serialBoards, vec![
+            fixture_uno("Arduino/Genuino Uno", "2341:0043 - 85736323838351F0B1C1"),
+            fixture_converter("1A86:7523 - "),
+        ]);
+        assert!(board_list.networkBoards.is_empty());
+    }
+
+    #[test]
+    fn fixture_0_13() {
+        let board_list = board_list_from_json(include_str!("fixtures/board_list_0.13.json"), Version::new(0, 13, 0)).unwrap();
+        let yun = NetworkBoard {
+            name: String::from("Arduino Yún"), fqbn: String::from("arduino:avr:yun"),
+            address: String::from("192.168.1.17"), port: None, protocol: String::from("network"),
+        };
+
+        assert_eq!(board_list.serialBoards, vec![fixture_uno("Arduino Uno", "")]);
+        assert_eq!(board_list.networkBoards, vec![yun]);
+    }
+
+    #[test]
+    fn fixture_0_18() {
+        let board_list = board_list_from_json(include_str!("fixtures/board_list_0.18.json"), Version::new(0, 18, 3)).unwrap();
+
+        assert_eq!(board_list.serialBoards, vec![fixture_uno("Arduino Uno", "2341:0043")]);
+        assert!(board_list.networkBoards.is_empty());
+    }
+
+    #[test]
+    fn fixture_0_21() {
+        let board_list = board_list_from_json(include_str!("fixtures/board_list_0.21.json"), Version::new(0, 21, 1)).unwrap();
+
+        assert_eq!(board_list.serialBoards, vec![
+            fixture_uno("Arduino Uno", "2341:0043"),
+            fixture_converter("1A86:7523"),
+        ]);
+        assert_eq!(board_list.networkBoards, vec![network_board()]);
+    }
+
+    #[test]
+    fn fixture_1_0() {
+        let board_list = board_list_from_json(include_str!("fixtures/board_list_1.0.json"), Version::new(1, 0, 4)).unwrap();
+
+        assert_eq!(board_list.serialBoards, vec![fixture_uno("Arduino Uno", "2341:0043")]);
+        assert_eq!(board_list.networkBoards, vec![network_board()]);
+    }
+
+    #[test]
+    fn mismatched_version() {
+        let board_list = board_list_from_json(include_str!("fixtures/board_list_1.0.json"), Version::new(0, 21, 1)).unwrap();
+
+        assert_eq!(board_list.serialBoards, vec![fixture_uno("Arduino Uno", "2341:0043")]);
+        assert_eq!(board_list.networkBoards, vec![network_board()]);
+
+        let board_list = board_list_from_json(include_str!("fixtures/board_list_0.3.json"), Version::new(1, 0, 4)).unwrap();
+
+        assert_eq!(board_list.serialBoards, vec![
+            fixture_uno("Arduino/Genuino Uno", "2341:0043 - 85736323838351F0B1C1"),
+            fixture_converter("1A86:7523 - "),
+        ]);
+    }
+
+    #[test]
+    fn no_detected_ports() {
+        assert!(listed_boards(board_list_from_json("{}", Version::new(1, 0, 4)).unwrap()).is_empty());
+        assert!(listed_boards(board_list_from_json("[]", Version::new(0, 21, 1)).unwrap()).is_empty());
+        assert!(listed_boards(board_list_from_json("null", Version::new(0, 21, 1)).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn targets() {
+        let serial = ListedBoard::Serial(some_board());
+        let network = ListedBoard::Network(network_board());
+
+        assert_eq!((serial.address(), serial.protocol()), ("C", "serial"));
+        assert_eq!((network.address(), network.protocol()), ("192.168.1.17", "network"));
+        assert!(!network.has_unknown_core());
+    }
+
+    #[test]
+    fn core_id() {
+        let mut board = some_board();
+        board.fqbn = String::from("arduino:avr:nano:cpu=atmega328old");
+
+        assert_eq!(board.core_id(), Some("arduino:avr"));
+        assert_eq!(coreless_board().core_id(), None);
+    }
+
     #[test]
     fn empty_json() {
         let err = boards_from_json("").unwrap_err();
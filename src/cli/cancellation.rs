@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use super::{Error, FailedCommand};
+
+/// A token which stops the commands it is passed to, when it is cancelled from any thread.
+///
+/// Clones of a token share their state, so a command can be cancelled via a clone:
+///
+/// ```no_run
+/// use std::thread;
+/// use arduinors::cli::{ArduinoCli, CancellationToken};
+///
+/// let token = CancellationToken::new();
+/// let cli = ArduinoCli::new().cancellation(token.clone());
+///
+/// thread::spawn(move || cli.install_core("arduino:avr"));
+/// token.cancel();
+/// ```
+///
+/// Once cancelled, a token stays cancelled, so any further commands it is passed to fail
+/// immediately.
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl PartialEq for CancellationToken {
+
+    /// Tokens are equal if they are clones of each other.
+    fn eq(&self, other: &CancellationToken) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+impl CancellationToken {
+
+    pub fn new() -> CancellationToken { CancellationToken::default() }
+
+    /// Stops all running commands this token was passed to, and prevents new ones from running.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// The conditions under which a command is stopped before it finishes.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub(super) struct Limits {
+    pub(super) timeout: Option<Duration>,
+    pub(super) cancellation: Option<CancellationToken>,
+}
+
+/// Tracks the limits of a running command.
+pub(super) struct Watch<'a> {
+    deadline: Option<Instant>,
+    tokens: Vec<&'a CancellationToken>,
+}
+
+impl<'a> Watch<'a> {
+
+    /// Starts watching a command which was just started under the given limits, where the first
+    /// timeout takes precedence.
+    pub(super) fn start(limits: &'a [&'a Limits]) -> Watch<'a> {
+        let timeout = limits.iter().find_map(|limits| limits.timeout);
+
+        Watch {
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            tokens: limits.iter().filter_map(|limits| limits.cancellation.as_ref()).collect(),
+        }
+    }
+
+    /// Indicates whether there is anything to watch.
+    pub(super) fn is_limited(&self) -> bool {
+        self.deadline.is_some() || !self.tokens.is_empty()
+    }
+
+    /// The error with which the command has to be stopped, if any of its limits has been reached.
+    pub(super) fn exceeded(&self) -> Option<fn(FailedCommand) -> Error> {
+        if self.tokens.iter().any(|token| token.is_cancelled()) {
+            Some(Error::Cancelled)
+        } else if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Some(Error::Timeout)
+        } else {
+            None
+        }
+    }
+}
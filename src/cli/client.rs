@@ -0,0 +1,600 @@
+use std::io;
+use std::ops::ControlFlow;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, ExitStatus};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use super::{Error, FailedCommand, OutputEvent, Stream, Activity, CancellationToken};
+use super::cancellation::{Limits, Watch};
+use super::stream::{spawn_streaming, output_lines, percentage};
+use super::version::{Version, check_supported};
+
+/// The client used by the `cli` module's free functions.
+static DEFAULT_CLIENT: OnceLock<ArduinoCli> = OnceLock::new();
+
+/// Runs the commands built by an `ArduinoCli` client.
+///
+/// The default runner spawns them as processes. Other runners can be used to observe or fake the
+/// Arduino CLI, e.g. `FakeRunner` for tests.
+pub trait Runner: Send + Sync {
+
+    /// Runs the given command to completion, capturing its stdout and stderr.
+    fn run(&self, command: Command) -> io::Result<Output>;
+
+    /// Runs the given command like `run`, additionally passing each line it prints to the given
+    /// callback. The callback should also be called regularly while the command prints nothing,
+    /// as that is when timeouts and cancellation are checked. If the callback breaks, the command
+    /// is stopped, and its output up to that point is returned. If it can not be stopped, an error
+    /// is returned instead.
+    ///
+    /// The default implementation passes the lines once the command has completed, stdout first,
+    /// so commands can not be stopped while they are running.
+    fn run_streaming(&self, command: Command, on_activity: &mut dyn FnMut(Activity) -> ControlFlow<()>) -> io::Result<Output> {
+        let output = self.run(command)?;
+
+        let stdout = output_lines(&output.stdout).into_iter().map(|line| (Stream::Stdout, line));
+        let stderr = output_lines(&output.stderr).into_iter().map(|line| (Stream::Stderr, line));
+
+        for (stream, line) in stdout.chain(stderr) {
+            if on_activity(Activity::Line(stream, &line)).is_break() { break; }
+        }
+
+        Ok(output)
+    }
+}
+
+/// A runner which spawns commands as processes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcessRunner;
+
+impl Runner for ProcessRunner {
+
+    fn run(&self, mut command: Command) -> io::Result<Output> {
+        command.output()
+    }
+
+    fn run_streaming(&self, command: Command, on_activity: &mut dyn FnMut(Activity) -> ControlFlow<()>) -> io::Result<Output> {
+        spawn_streaming(command, on_activity)
+    }
+}
+
+/// A client for a specific installation and configuration of the Arduino CLI.
+///
+/// The `cli` module's free functions use a default client, which runs the `arduino-cli` binary
+/// found on the `PATH` with its default configuration. The same operations are available as
+/// methods on a client, which can be configured using its builder methods:
+///
+/// ```no_run
+/// use arduinors::cli::ArduinoCli;
+///
+/// let cli = ArduinoCli::new()
+///     .binary("/opt/arduino/arduino-cli")
+///     .config_file("ci/arduino-cli.yaml")
+///     .data_dir("target/arduino");
+///
+/// let cores = cli.core_list_all();
+/// ```
+///
+/// Cloned clients share their runner and the detected version of the Arduino CLI. So a clone
+/// with a different timeout can be used for a single call:
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use std::time::Duration;
+/// # use arduinors::cli::{self, ArduinoCli};
+/// # let cli = ArduinoCli::new();
+/// # let board = &cli::board_list_serial().unwrap()[0];
+/// cli.clone().timeout(Duration::from_secs(30)).upload(Path::new("Blink"), board)?;
+/// # Ok::<(), cli::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct ArduinoCli {
+    binary: PathBuf,
+    config_file: Option<PathBuf>,
+    env: Vec<(OsString, OsString)>,
+    pub(super) limits: Limits,
+    runner: Arc<dyn Runner>,
+    version: Arc<OnceLock<Version>>,
+}
+
+impl ArduinoCli {
+
+    /// A client running the `arduino-cli` binary found on the `PATH`, with its default
+    /// configuration.
+    pub fn new() -> ArduinoCli {
+        ArduinoCli {
+            binary: PathBuf::from("arduino-cli"),
+            config_file: None,
+            env: vec![],
+            limits: Limits::default(),
+            runner: Arc::new(ProcessRunner),
+            version: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Sets the path of the Arduino CLI binary.
+    pub fn binary<P: Into<PathBuf>>(mut self, path: P) -> ArduinoCli {
+        self.binary = path.into();
+        self
+    }
+
+    /// Sets the configuration file passed via `--config-file`.
+    pub fn config_file<P: Into<PathBuf>>(mut self, path: P) -> ArduinoCli {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// Sets the directory in which cores and their tools are installed (`directories.data`).
+    pub fn data_dir<P: AsRef<Path>>(self, path: P) -> ArduinoCli {
+        self.env("ARDUINO_DIRECTORIES_DATA", path.as_ref())
+    }
+
+    /// Sets the sketchbook directory, in which libraries are installed (`directories.user`).
+    pub fn user_dir<P: AsRef<Path>>(self, path: P) -> ArduinoCli {
+        self.env("ARDUINO_DIRECTORIES_USER", path.as_ref())
+    }
+
+    /// Sets the directory in which downloaded archives are stored (`directories.downloads`).
+    pub fn downloads_dir<P: AsRef<Path>>(self, path: P) -> ArduinoCli {
+        self.env("ARDUINO_DIRECTORIES_DOWNLOADS", path.as_ref())
+    }
+
+    /// Adds an environment variable, which is set for every command run by the client.
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, value: V) -> ArduinoCli {
+        self.env.push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
+    /// Sets how long any command run by the client may take, before it is killed along with the
+    /// processes it started. Options of a call which set a timeout take precedence.
+    pub fn timeout(mut self, timeout: Duration) -> ArduinoCli {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a token which stops any command run by the client when it is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> ArduinoCli {
+        self.limits.cancellation = Some(token);
+        self
+    }
+
+    /// Sets the runner used to run the client's commands.
+    pub fn runner<R: Runner + 'static>(mut self, runner: R) -> ArduinoCli {
+        self.runner = Arc::new(runner);
+        self.version = Arc::new(OnceLock::new());
+        self
+    }
+
+    /// Makes this client the one used by the `cli` module's free functions, including those of
+    /// `cli::nonblocking`, e.g. so that its timeout applies to all of them.
+    ///
+    /// # Errors
+    /// Returns the client, if a free function has been called or a default client has been set
+    /// before, as the default client can not be replaced.
+    pub fn set_default(self) -> Result<(), ArduinoCli> {
+        DEFAULT_CLIENT.set(self)
+    }
+
+    /// A command running the Arduino CLI with the given arguments and the client's configuration.
+    pub(super) fn command<I, S>(&self, args: I) -> Command
+    where I: IntoIterator<Item = S>, S: AsRef<OsStr> {
+        let mut command = Command::new(&self.binary);
+        command.args(args).envs(self.env.iter().map(|(key, value)| (key, value)));
+
+        if let Some(config_file) = &self.config_file {
+            command.arg("--config-file").arg(config_file);
+        }
+
+        command
+    }
+
+    /// Runs the given command via the client's runner, returning its output if it succeeded.
+    ///
+    /// # Errors
+    /// * `SpawnFailure`, if the command could not be run.
+    /// * `CommandFailure`, if the command exited with a non-zero status.
+    /// * `Timeout` or `Cancelled`, if the client's timeout passed or its cancellation token was
+    ///   cancelled.
+    /// * `KillFailure`, if the command had to be stopped, but the runner failed to do so.
+    pub(super) fn run(&self, command: Command) -> Result<Output, Error> {
+        self.run_limited(command, &Limits::default())
+    }
+
+    /// Runs the given command like `run`, but also stops it if the given limits of the call say
+    /// so. The call's timeout takes precedence over the client's.
+    pub(super) fn run_limited(&self, command: Command, limits: &Limits) -> Result<Output, Error> {
+        let all_limits = [limits, &self.limits];
+
+        if Watch::start(&all_limits).is_limited() {
+            self.run_streaming(command, limits, false, &mut |_| ControlFlow::Continue(()))
+        } else {
+            check_output(command_line(&command), self.runner.run(command))
+        }
+    }
+
+    /// Runs the given command via the client's runner like `run_limited`, passing each line it
+    /// prints to the given callback. Percentages are only parsed from the lines if `progress` is
+    /// set, as only some commands print progress bars.
+    ///
+    /// # Errors
+    /// * as described for `run`.
+    /// * `Cancelled`, if the callback stopped the command.
+    pub(super) fn run_streaming(
+        &self, command: Command, limits: &Limits, progress: bool, on_event: &mut dyn FnMut(&OutputEvent) -> ControlFlow<()>,
+    ) -> Result<Output, Error> {
+        let command_line = command_line(&command);
+        let all_limits = [limits, &self.limits];
+        let watch = Watch::start(&all_limits);
+
+        // A command is not started if it has been cancelled already.
+        if let Some(error) = watch.exceeded() { return Err(error(FailedCommand::stopped(command_line))); }
+
+        let mut stop: Option<fn(FailedCommand) -> Error> = None;
+
+        let output = self.runner.run_streaming(command, &mut |activity| {
+            if let Activity::Line(stream, line) = activity {
+                let percentage = if progress { percentage(line) } else { None };
+
+                if on_event(&OutputEvent { stream, line: String::from(line), percentage }).is_break() {
+                    stop = Some(Error::Cancelled);
+                    return ControlFlow::Break(());
+                }
+            }
+
+            stop = watch.exceeded();
+            if stop.is_some() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        });
+
+        match (output, stop) {
+            (Ok(output), Some(error)) => Err(error(failed_command(command_line, &output))),
+            (Err(error), Some(_)) => Err(Error::KillFailure { command: command_line, message: error.to_string() }),
+            (output, None) => check_output(command_line, output),
+        }
+    }
+
+    /// The version of the client's Arduino CLI, which is only detected on the first call.
+    ///
+    /// # Errors
+    /// * as described for `cli::version`.
+    /// * `UnsupportedVersion`, if the version is not supported.
+    pub(super) fn detected_version(&self) -> Result<Version, Error> {
+        match self.cached_version() {
+            Some(version) => check_supported(version),
+            None => check_supported(self.cache_version(self.version()?)),
+        }
+    }
+
+    /// Records the version of the client's Arduino CLI, if it has not been detected yet.
+    pub(super) fn cache_version(&self, version: Version) -> Version {
+        *self.version.get_or_init(|| version)
+    }
+
+    /// The version of the client's Arduino CLI, if it has been detected.
+    pub(super) fn cached_version(&self) -> Option<Version> {
+        self.version.get().copied()
+    }
+}
+
+impl Default for ArduinoCli {
+
+    fn default() -> ArduinoCli { ArduinoCli::new() }
+}
+
+/// The command line of a given command, with arguments quoted if they contain whitespace.
+pub(super) fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| {
+            let part = part.to_string_lossy();
+            if part.contains(char::is_whitespace) { format!("\"{}\"", part) } else { part.into_owned() }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Converts the output of the command with the given command line to a result, which is only
+/// successful if the command was run and succeeded.
+pub(super) fn check_output(command_line: String, output: io::Result<Output>) -> Result<Output, Error> {
+    match output {
+        Ok(output) if output.status.success() => Ok(output),
+        Ok(output) => Err(Error::CommandFailure(failed_command(command_line, &output))),
+        Err(error) => Err(Error::SpawnFailure { command: command_line, message: error.to_string() }),
+    }
+}
+
+/// The failure of the command with the given command line and output.
+pub(super) fn failed_command(command_line: String, output: &Output) -> FailedCommand {
+    FailedCommand {
+        command: command_line,
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
+
+/// The client used by the `cli` module's free functions.
+pub(super) fn default_client() -> &'static ArduinoCli {
+    DEFAULT_CLIENT.get_or_init(ArduinoCli::new)
+}
+
+/// A recorded response of a `FakeRunner`.
+#[derive(Clone)]
+struct Response {
+    args: Vec<String>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    code: i32,
+}
+
+/// A runner which does not run any commands, but responds with recorded outputs instead.
+///
+/// A response is chosen by the first arguments of a command, e.g. a response for `["board",
+/// "list"]` is returned for `arduino-cli board list --format json`. If multiple responses match,
+/// the one recorded first is used. Commands without a matching response fail as if the binary
+/// did not exist.
+///
+/// Clones of a fake runner share their responses and the commands they have received.
+#[derive(Clone, Default)]
+pub struct FakeRunner {
+    responses: Arc<Mutex<Vec<Response>>>,
+    commands: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl FakeRunner {
+
+    pub fn new() -> FakeRunner { FakeRunner::default() }
+
+    /// Records a response for commands starting with the given arguments, with the given stdout,
+    /// stderr and exit code.
+    pub fn respond(self, args: &[&str], stdout: &str, stderr: &str, code: i32) -> FakeRunner {
+        self.responses.lock().unwrap().push(Response {
+            args: args.iter().map(|arg| String::from(*arg)).collect(),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+            code,
+        });
+
+        self
+    }
+
+    /// Records a response to `arduino-cli version --format json`, reporting the given version.
+    pub fn respond_version(self, version: &str) -> FakeRunner {
+        let stdout = format!(r#"{{"Application": "arduino-cli", "VersionString": "{}"}}"#, version);
+        self.respond(&["version"], &stdout, "", 0)
+    }
+
+    /// The arguments of all commands received so far, in the order they were received.
+    pub fn commands(&self) -> Vec<Vec<String>> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+impl Runner for FakeRunner {
+
+    fn run(&self, command: Command) -> io::Result<Output> {
+        let args: Vec<String> = command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        self.commands.lock().unwrap().push(args.clone());
+
+        let responses = self.responses.lock().unwrap();
+        let response = responses
+            .iter()
+            .find(|response| args.starts_with(&response.args))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+        Ok(Output {
+            status: exit_status(response.code),
+            stdout: response.stdout.clone(),
+            stderr: response.stderr.clone(),
+        })
+    }
+}
+
+/// An exit status with the given exit code.
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw(code << 8)
+}
+
+/// An exit status with the given exit code.
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::InstallOptions;
+
+    #[test]
+    fn configured_command() {
+        let cli = ArduinoCli::new()
+            .binary("/opt/arduino-cli")
+            .config_file("arduino-cli.yaml")
+            .data_dir("data");
+
+        let command = cli.command(["core", "list"]);
+        let args: Vec<_> = command.get_args().collect();
+        let envs: Vec<_> = command.get_envs().collect();
+
+        assert_eq!(command.get_program(), "/opt/arduino-cli");
+        assert_eq!(args, ["core", "list", "--config-file", "arduino-cli.yaml"]);
+        assert_eq!(envs, [(OsStr::new("ARDUINO_DIRECTORIES_DATA"), Some(OsStr::new("data")))]);
+    }
+
+    #[test]
+    fn command_failure() {
+        let runner = FakeRunner::new()
+            .respond_version("1.0.4")
+            .respond(&["core", "install"], "Downloading index...", "Error: platform not found\n", 1);
+        let cli = ArduinoCli::new().binary("/opt/arduino cli").runner(runner);
+
+        let error = cli.run(cli.command(["core", "install", "arduino:xyz"])).unwrap_err();
+        let failure = match &error { Error::CommandFailure(failure) => failure, _ => panic!() };
+
+        assert_eq!(failure.command(), "\"/opt/arduino cli\" core install arduino:xyz");
+        assert_eq!((failure.exit_code(), failure.stdout()), (Some(1), "Downloading index..."));
+        assert_eq!(error.to_string(), "`\"/opt/arduino cli\" core install arduino:xyz` exited with status 1:\nError: platform not found");
+    }
+
+    #[test]
+    fn spawn_failure() {
+        let cli = ArduinoCli::new().runner(FakeRunner::new());
+
+        let error = cli.run(cli.command(["version"])).unwrap_err();
+
+        assert!(matches!(error, Error::SpawnFailure { ref command, .. } if command == "arduino-cli version"));
+    }
+
+    #[test]
+    fn streamed_events() {
+        let stdout = "Downloading packages...\rarduino:avr-gcc@7.3.0 12.00 MiB / 32.17 MiB  37.30%\rInstalled\n";
+        let runner = FakeRunner::new().respond_version("1.0.4").respond(&["core", "install"], stdout, "", 0);
+        let cli = ArduinoCli::new().runner(runner);
+        let mut events = vec![];
+
+        cli.install_core_streaming("arduino:avr", &InstallOptions::new(), |event| {
+            events.push((event.line().to_owned(), event.percentage()));
+            ControlFlow::Continue(())
+        }).unwrap();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].1, Some(37.3));
+    }
+
+    #[test]
+    fn cancelled_command() {
+        let runner = FakeRunner::new().respond_version("1.0.4").respond(&["core", "install"], "Downloading\n", "", 0);
+        let cli = ArduinoCli::new().runner(runner);
+
+        let error = cli.install_core_streaming("arduino:avr", &InstallOptions::new(), |_| ControlFlow::Break(())).unwrap_err();
+
+        assert!(matches!(error, Error::Cancelled(ref failure) if failure.stdout() == "Downloading\n"));
+    }
+
+    #[test]
+    fn cancelled_by_options() {
+        let stdout = "Downloading 10%\rDownloading 20%\rDownloading 30%\n";
+        let runner = FakeRunner::new().respond_version("1.0.4").respond(&["core", "install"], stdout, "", 0);
+        let cli = ArduinoCli::new().runner(runner);
+        let token = CancellationToken::new();
+        let options = InstallOptions::new().cancellation(token.clone());
+        let mut events = 0;
+
+        let error = cli.install_core_streaming("arduino:avr", &options, |_| {
+            events += 1;
+            token.cancel();
+            ControlFlow::Continue(())
+        }).unwrap_err();
+
+        assert!(matches!(error, Error::Cancelled(_)));
+        assert_eq!(events, 1);
+    }
+
+    /// A runner whose commands can not be stopped.
+    struct UnstoppableRunner;
+
+    impl Runner for UnstoppableRunner {
+
+        fn run(&self, _: Command) -> io::Result<Output> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn run_streaming(&self, _: Command, on_activity: &mut dyn FnMut(Activity) -> ControlFlow<()>) -> io::Result<Output> {
+            let _ = on_activity(Activity::Line(Stream::Stdout, "Uploading"));
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "operation not permitted"))
+        }
+    }
+
+    #[test]
+    fn kill_failure() {
+        let cli = ArduinoCli::new().runner(UnstoppableRunner);
+
+        let error = cli.run_streaming(cli.command(["upload"]), &Limits::default(), false, &mut |_| ControlFlow::Break(())).unwrap_err();
+
+        assert!(matches!(error, Error::KillFailure { ref command, .. } if command == "arduino-cli upload"));
+        assert_eq!(error.to_string(), "failed to stop `arduino-cli upload`, which may still be running: operation not permitted");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_timeout() {
+        let cli = ArduinoCli::new().binary("sh").timeout(Duration::from_millis(100));
+
+        let error = cli.run(cli.command(["-c", "echo waiting; sleep 10"])).unwrap_err();
+
+        assert!(matches!(error, Error::Timeout(ref failure) if failure.stdout() == "waiting\n"));
+        assert_eq!(error.to_string(), "`sh -c \"echo waiting; sleep 10\"` timed out");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn call_timeout_precedence() {
+        let cli = ArduinoCli::new().binary("sh").timeout(Duration::from_millis(100));
+        let limits = Limits { timeout: Some(Duration::from_secs(10)), cancellation: None };
+
+        assert!(cli.run_limited(cli.command(["-c", "sleep 0.3"]), &limits).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancelled_from_thread() {
+        let token = CancellationToken::new();
+        let cli = ArduinoCli::new().binary("sh").cancellation(token.clone());
+
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        });
+        let result = cli.run(cli.command(["-c", "sleep 10"]));
+        canceller.join().unwrap();
+
+        assert!(matches!(result, Err(Error::Cancelled(_))));
+    }
+
+    #[test]
+    fn cancelled_before_start() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let runner = FakeRunner::new().respond_version("1.0.4");
+        let cli = ArduinoCli::new().runner(runner.clone()).cancellation(token);
+
+        assert!(matches!(cli.version(), Err(Error::Cancelled(_))));
+        assert!(runner.commands().is_empty());
+    }
+
+    #[test]
+    fn fake_runner() {
+        let runner = FakeRunner::new().respond(&["core", "install"], "done", "warning", 3);
+        let cli = ArduinoCli::new();
+
+        let output = runner.run(cli.command(["core", "install", "arduino:avr"])).unwrap();
+        let missing = runner.run(cli.command(["core", "list"]));
+
+        assert_eq!((output.stdout.as_slice(), output.stderr.as_slice()), (&b"done"[..], &b"warning"[..]));
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(runner.commands().len(), 2);
+    }
+
+    #[test]
+    fn detects_version_once() {
+        let runner = FakeRunner::new().respond_version("0.35.3");
+        let cli = ArduinoCli::new().runner(runner.clone());
+
+        assert_eq!(cli.detected_version(), Ok(Version::new(0, 35, 3)));
+        assert_eq!(cli.clone().detected_version(), Ok(Version::new(0, 35, 3)));
+        assert_eq!(runner.commands(), [["version", "--format", "json"]]);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let cli = ArduinoCli::new().runner(FakeRunner::new().respond_version("0.2.2"));
+
+        assert_eq!(cli.detected_version(), Err(Error::UnsupportedVersion(Version::new(0, 2, 2))));
+    }
+}
@@ -1,19 +1,63 @@
 use std::str;
+use std::collections::HashMap;
 use std::process;
+use std::ops::ControlFlow;
 use std::process::Command;
 use serde::{Serialize, Deserialize};
 use serde_json as json;
 
-use super::Error;
+use super::{Error, OutputEvent, InstallOptions};
+use super::client::{ArduinoCli, default_client};
+use super::version::{Version, DETECTED_PORTS_VERSION, WRAPPED_OUTPUT_VERSION};
 
+/// A wrapper for the result of calling `arduino-cli core search --format json`.
+///
+/// Versions of the Arduino CLI before 0.11 wrap the cores in an object with a `Platforms` key, and
+/// versions since 0.36 in an object with a `platforms` key. Versions in between print a bare
+/// (possibly `null`) list.
 #[derive(Serialize, Deserialize)]
-#[allow(non_snake_case)]
 struct CoreList {
-    Platforms: Vec<Core>
+    #[serde(alias = "Platforms", default)]
+    platforms: Vec<Core>,
+}
+
+/// A core as printed by any version of the Arduino CLI, which is converted to a `Core`.
+///
+/// The name of a core is not printed as part of it since version 0.36, but only as part of its
+/// releases.
+#[derive(Deserialize)]
+struct CoreFormat {
+    #[serde(alias = "ID")]
+    id: String,
+    #[serde(alias = "Version", alias = "Latest", alias = "latest", alias = "latest_version")]
+    version: String,
+    #[serde(alias = "Name")]
+    name: Option<String>,
+    #[serde(default)]
+    releases: HashMap<String, ReleaseFormat>,
+}
+
+/// A release of a core, as printed by versions of the Arduino CLI since 0.36.
+#[derive(Deserialize)]
+struct ReleaseFormat {
+    name: String,
+}
+
+impl From<CoreFormat> for Core {
+
+    fn from(format: CoreFormat) -> Core {
+        let CoreFormat { id, version, name, mut releases } = format;
+        let name = name
+            .or_else(|| releases.remove(&version).map(|release| release.name))
+            .unwrap_or_default();
+
+        Core { ID: id, Version: version, Name: name }
+    }
 }
 
 /// A container for a line in the output produced by `arduino-cli core search ''`.
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(from = "CoreFormat")]
 #[allow(non_snake_case)]
 pub struct Core {
     ID: String,
@@ -31,53 +75,118 @@ impl Core {
 }
 
 pub fn install_core(id: &str) -> Result<(), Error> {
-    Command::new("arduino-cli")
-        .args(&["core", "install", id])
-        .stdout(process::Stdio::null())
-        .stderr(process::Stdio::null())
-        .status()
-        .map_err(|_| Error::CommandFailure)
-        .and_then(|status| {
-            if status.success() { Ok(()) } else { Err(Error::CommandFailure) }
-        })
+    default_client().install_core(id)
+}
+
+/// Installs the core with the given ID like `cli::install_core`, passing each line printed by the
+/// Arduino CLI to the given callback while installing, along with the progress of downloads. If
+/// the callback breaks, installation is stopped.
+///
+/// # Errors
+/// * `Cancelled`, if the callback or the options' cancellation token stopped installation.
+/// * `Timeout`, if installation was stopped because of the options' or the client's timeout.
+pub fn install_core_streaming<F>(id: &str, options: &InstallOptions, on_event: F) -> Result<(), Error>
+where F: FnMut(&OutputEvent) -> ControlFlow<()> {
+    default_client().install_core_streaming(id, options, on_event)
 }
 
 pub fn update_core_index() -> Result<(), Error> {
-    Command::new("arduino-cli")
-        .args(&["core", "update-index"])
-        .output()
-        .map(|_| ())
-        .map_err(|_| Error::CommandFailure)
+    default_client().update_core_index()
 }
 
 pub fn core_list_all() -> Result<Vec<Core>, Error> {
-    // Asks the Arduino CLI for a list of all Arduino cores in JSON format.
-    let command_stdout = Command::new("arduino-cli")
-        .args(&["core", "search", "''", "--format", "json"])
-        .output()
-        .map(|output| output.stdout);
-
-    if let Ok(stdout) = command_stdout {
-        // The command line output has to be converted to a valid UTF-8 string before being able to
-        // use it.
-        str::from_utf8(&stdout)
-            .map_err(|_| Error::CommandFailure)
-            .and_then(cores_from_json)
+    default_client().core_list_all()
+}
+
+impl ArduinoCli {
+
+    /// Installs the core with the given ID, as with `cli::install_core`.
+    pub fn install_core(&self, id: &str) -> Result<(), Error> {
+        self.detected_version()?;
+        self.run(install_core_command(self, id)).map(|_| ())
+    }
+
+    /// Installs the core with the given ID, passing the printed lines to the given callback, as
+    /// with `cli::install_core_streaming`.
+    pub fn install_core_streaming<F>(&self, id: &str, options: &InstallOptions, mut on_event: F) -> Result<(), Error>
+    where F: FnMut(&OutputEvent) -> ControlFlow<()> {
+        self.detected_version()?;
+        self.run_streaming(install_core_command(self, id), &options.limits, true, &mut on_event).map(|_| ())
+    }
+
+    /// Updates the Arduino CLI's index of cores, as with `cli::update_core_index`.
+    pub fn update_core_index(&self) -> Result<(), Error> {
+        self.detected_version()?;
+
+        // Failing to update the index is not an error, as the previous index remains usable.
+        match self.run(update_core_index_command(self)) {
+            Ok(_) | Err(Error::CommandFailure(_)) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Lists all Arduino cores, as with `cli::core_list_all`.
+    pub fn core_list_all(&self) -> Result<Vec<Core>, Error> {
+        let version = self.detected_version()?;
+        cores_from_output(self.run(core_list_all_command(self, version)), version)
+    }
+}
+
+/// The command which asks the Arduino CLI to install the core with the given ID.
+pub(super) fn install_core_command(cli: &ArduinoCli, id: &str) -> Command {
+    cli.command(["core", "install", id])
+}
+
+/// The command which asks the Arduino CLI to update its index of cores.
+pub(super) fn update_core_index_command(cli: &ArduinoCli) -> Command {
+    cli.command(["core", "update-index"])
+}
+
+/// The command which asks the Arduino CLI for a list of all Arduino cores in JSON format.
+pub(super) fn core_list_all_command(cli: &ArduinoCli, version: Version) -> Command {
+    // Older versions require a search term, while newer ones list all cores without one.
+    if version < DETECTED_PORTS_VERSION {
+        cli.command(["core", "search", "''", "--format", "json"])
     } else {
-        Err(Error::CommandFailure)
+        cli.command(["core", "search", "--format", "json"])
     }
 }
 
-fn cores_from_json(core_json: &str) -> Result<Vec<Core>, Error> {
-    json::from_str(core_json)
-        .map(|core_list: CoreList| core_list.Platforms)
+/// Converts the output of the core list command into core instances.
+pub(super) fn cores_from_output(output: Result<process::Output, Error>, version: Version) -> Result<Vec<Core>, Error> {
+    // The command line output has to be converted to a valid UTF-8 string before being able to
+    // use it.
+    str::from_utf8(&output?.stdout)
         .map_err(|_| Error::UnknownFormat)
+        .and_then(|core_json| cores_from_json(core_json, version))
+}
+
+/// Converts a given output from `arduino-cli core search --format json` into core instances, using
+/// the format printed by the given version of the Arduino CLI.
+///
+/// If the output does not have that format (e.g. because it changed in an unexpected version), the
+/// other format is tried. As any object is read as an empty wrapped list, only non-empty wrapped
+/// lists are accepted this way.
+fn cores_from_json(core_json: &str, version: Version) -> Result<Vec<Core>, Error> {
+    let wrapped = || json::from_str(core_json).map(|core_list: CoreList| core_list.platforms).ok();
+    let bare = || json::from_str(core_json).map(|cores: Option<Vec<Core>>| cores.unwrap_or_default()).ok();
+
+    let cores = if version < DETECTED_PORTS_VERSION || version >= WRAPPED_OUTPUT_VERSION {
+        wrapped().or_else(bare)
+    } else {
+        bare().or_else(|| wrapped().filter(|cores| !cores.is_empty()))
+    };
+
+    cores.ok_or(Error::UnknownFormat)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A version of the Arduino CLI which wraps its JSON output.
+    const CURRENT: Version = Version::new(1, 0, 4);
+
     fn core_1() -> Core {
         Core { ID: String::from("A"), Version: String::from("B"), Name: String::from("C") }
     }
@@ -88,16 +197,16 @@ mod tests {
 
     /// A convenience function for creating the JSON-string, as would be printed by `arduino-cli
     /// core search '' --format json`, for a given list of cores.
-    fn json_for_cores(cores: &Vec<Core>) -> String {
-        let core_list = CoreList { Platforms: cores.clone() };
-        String::from(json::json!(core_list).to_string())
+    fn json_for_cores(cores: &[Core]) -> String {
+        let core_list = CoreList { platforms: cores.to_vec() };
+        json::json!(core_list).to_string()
     }
 
     #[test]
     fn no_cores() {
-        let no_core_json = &json_for_cores(&vec![]);
+        let no_core_json = &json_for_cores(&[]);
 
-        let result = cores_from_json(no_core_json).unwrap();
+        let result = cores_from_json(no_core_json, CURRENT).unwrap();
 
         assert!(result.is_empty());
     }
@@ -107,7 +216,7 @@ mod tests {
         let cores = vec![core_1()];
         let one_core_json = &json_for_cores(&cores);
 
-        let result = cores_from_json(one_core_json).unwrap();
+        let result = cores_from_json(one_core_json, CURRENT).unwrap();
 
         assert_eq!(result, cores);
     }
@@ -117,14 +226,73 @@ mod tests {
         let cores = vec![core_1(), core_2()];
         let multi_core_json = &json_for_cores(&cores);
 
-        let result = cores_from_json(multi_core_json).unwrap();
+        let result = cores_from_json(multi_core_json, CURRENT).unwrap();
 
         assert_eq!(result, cores);
     }
 
+    /// The AVR and SAMD cores, which are listed in all fixtures, with the given versions.
+    fn fixture_cores(avr_version: &str, samd_version: &str) -> Vec<Core> {
+        vec![
+            Core {
+                ID: String::from("arduino:avr"), Version: String::from(avr_version),
+                Name: String::from("Arduino AVR Boards"),
+            },
+            Core {
+                ID: String::from("arduino:samd"), Version: String::from(samd_version),
+                Name: String::from("Arduino SAMD Boards (32-bits ARM Cortex-M0+)"),
+            },
+        ]
+    }
+
+    #[test]
+    fn fixture_0_3() {
+        let result = cores_from_json(include_str!("fixtures/core_search_0.3.json"), Version::new(0, 3, 6)).unwrap();
+
+        assert_eq!(result, fixture_cores("1.6.23", "1.6.20"));
+    }
+
+    #[test]
+    fn fixture_0_13() {
+        let result = cores_from_json(include_str!("fixtures/core_search_0.13.json"), Version::new(0, 13, 0)).unwrap();
+
+        assert_eq!(result, fixture_cores("1.8.3", "1.8.9"));
+    }
+
+    #[test]
+    fn fixture_0_21() {
+        let result = cores_from_json(include_str!("fixtures/core_search_0.21.json"), Version::new(0, 21, 1)).unwrap();
+
+        assert_eq!(result, fixture_cores("1.8.5", "1.8.12"));
+    }
+
+    #[test]
+    fn fixture_1_0() {
+        let result = cores_from_json(include_str!("fixtures/core_search_1.0.json"), Version::new(1, 0, 4)).unwrap();
+
+        assert_eq!(result, fixture_cores("1.8.6", "1.8.14"));
+    }
+
+    #[test]
+    fn no_search_results() {
+        assert!(cores_from_json("null", Version::new(0, 21, 1)).unwrap().is_empty());
+        assert!(cores_from_json("{}", CURRENT).unwrap().is_empty());
+    }
+
+    #[test]
+    fn mismatched_version() {
+        let result = cores_from_json(include_str!("fixtures/core_search_1.0.json"), Version::new(0, 21, 1)).unwrap();
+
+        assert_eq!(result, fixture_cores("1.8.6", "1.8.14"));
+
+        let result = cores_from_json(include_str!("fixtures/core_search_0.21.json"), CURRENT).unwrap();
+
+        assert_eq!(result, fixture_cores("1.8.5", "1.8.12"));
+    }
+
     #[test]
     fn empty_json() {
-        let err = cores_from_json("").unwrap_err();
+        let err = cores_from_json("", CURRENT).unwrap_err();
 
         assert_eq!(err, Error::UnknownFormat);
     }
@@ -133,7 +301,7 @@ mod tests {
     fn malformed_json() {
         let malformed_json = r#"{"Platforms": [{"xyz": "0123"}]}"#;
 
-        let err = cores_from_json(malformed_json).unwrap_err();
+        let err = cores_from_json(malformed_json, CURRENT).unwrap_err();
 
         assert_eq!(err, Error::UnknownFormat);
     }
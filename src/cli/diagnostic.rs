@@ -0,0 +1,218 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+/// The markers separating the location of a GCC diagnostic from its message, along with the
+/// severities they indicate.
+const GCC_SEVERITY_MARKERS: [(&str, Severity); 4] = [
+    (": fatal error: ", Severity::Error),
+    (": error: ", Severity::Error),
+    (": warning: ", Severity::Warning),
+    (": note: ", Severity::Note),
+];
+
+/// The severity of a diagnostic.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Additional information about a preceding error or warning.
+    Note,
+}
+
+impl fmt::Display for Severity {
+
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(formatter, "error"),
+            Severity::Warning => write!(formatter, "warning"),
+            Severity::Note => write!(formatter, "note"),
+        }
+    }
+}
+
+/// An error, warning or note reported by the compiler for a location in a sketch's sources.
+///
+/// Locations in the C++ file generated from a sketch's `.ino` files are mapped back to the `.ino`
+/// files.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    file: PathBuf,
+    line: u32,
+    column: Option<u32>,
+    severity: Severity,
+    message: String,
+}
+
+impl Diagnostic {
+
+    pub fn file(&self) -> &Path { &self.file }
+
+    /// The line of the diagnostic, starting at 1.
+    pub fn line(&self) -> u32 { self.line }
+
+    /// The column of the diagnostic, starting at 1, if the compiler reported one.
+    pub fn column(&self) -> Option<u32> { self.column }
+
+    pub fn severity(&self) -> Severity { self.severity }
+
+    pub fn message(&self) -> &str { &self.message }
+}
+
+impl fmt::Display for Diagnostic {
+
+    /// Formats the diagnostic as GCC does, e.g. `Blink.ino:5:1: error: expected ';' before '}'`.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}:{}:", self.file.display(), self.line)?;
+
+        if let Some(column) = self.column {
+            write!(formatter, "{}:", column)?;
+        }
+
+        write!(formatter, " {}: {}", self.severity, self.message)
+    }
+}
+
+/// A diagnostic as printed in the JSON output of `arduino-cli compile` since version 0.33.
+#[derive(Deserialize)]
+pub(super) struct DiagnosticFormat {
+    severity: String,
+    message: String,
+    file: PathBuf,
+    line: u32,
+    #[serde(default)]
+    column: u32,
+    #[serde(default)]
+    notes: Vec<DiagnosticFormat>,
+}
+
+/// Converts diagnostics from the JSON output of the Arduino CLI, which contain their notes, into
+/// a flat list of diagnostics.
+pub(super) fn diagnostics_from_formats(formats: Vec<DiagnosticFormat>) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for format in formats {
+        let severity = match format.severity.to_lowercase().as_str() {
+            "warning" => Severity::Warning,
+            "note" => Severity::Note,
+            _ => Severity::Error,
+        };
+
+        diagnostics.push(Diagnostic {
+            file: format.file,
+            line: format.line,
+            column: if format.column == 0 { None } else { Some(format.column) },
+            severity,
+            message: format.message,
+        });
+
+        diagnostics.extend(diagnostics_from_formats(format.notes));
+    }
+
+    diagnostics
+}
+
+/// Parses the diagnostics printed by GCC, ignoring any other lines of the given output.
+pub(super) fn diagnostics_from_gcc_output(output: &str) -> Vec<Diagnostic> {
+    output.lines().filter_map(diagnostic_from_gcc_line).collect()
+}
+
+/// Parses a line of GCC output of the form `file:line:column: severity: message`, where the
+/// column is optional.
+fn diagnostic_from_gcc_line(line: &str) -> Option<Diagnostic> {
+    let (index, marker, severity) = GCC_SEVERITY_MARKERS
+        .iter()
+        .filter_map(|(marker, severity)| line.find(marker).map(|index| (index, marker, *severity)))
+        .min_by_key(|(index, _, _)| *index)?;
+
+    let location = line[..index].trim_start();
+    let message = line[index + marker.len()..].trim_end();
+
+    // The file itself may contain colons (e.g. `C:\`), so the location is split from the end.
+    let (file, last) = location.rsplit_once(':')?;
+    let last = last.parse().ok()?;
+
+    let (file, line, column) = match file.rsplit_once(':').map(|(file, line)| (file, line.parse())) {
+        Some((file, Ok(line))) => (file, line, Some(last)),
+        _ => (file, last, None),
+    };
+
+    Some(Diagnostic { file: PathBuf::from(file), line, column, severity, message: String::from(message) })
+}
+
+/// Maps a diagnostic in the C++ file generated from a sketch's `.ino` files back to the `.ino`
+/// file, using the generated file's `#line` directives.
+///
+/// Diagnostics in other files, or in generated files which no longer exist, are left unchanged.
+pub(super) fn map_to_sketch(diagnostic: Diagnostic) -> Diagnostic {
+    if !diagnostic.file.to_string_lossy().ends_with(".ino.cpp") { return diagnostic; }
+
+    match fs::read_to_string(&diagnostic.file).ok().and_then(|source| sketch_location(&source, diagnostic.line)) {
+        Some((file, line)) => Diagnostic { file, line, ..diagnostic },
+        None => diagnostic,
+    }
+}
+
+/// The location in a `.ino` file of the given line of a generated C++ source.
+fn sketch_location(source: &str, line: u32) -> Option<(PathBuf, u32)> {
+    // A `#line N "file"` directive indicates that the line following it is line N of the file.
+    let (directive_line, first_line, file) = source
+        .lines()
+        .zip(1..line)
+        .filter_map(|(text, number)| {
+            let (first_line, file) = text.strip_prefix("#line ")?.split_once(' ')?;
+            let file = file.strip_prefix('"')?.strip_suffix('"')?;
+            Some((number, first_line.parse::<u32>().ok()?, file))
+        })
+        .last()?;
+
+    Some((PathBuf::from(file.replace("\\\\", "\\")), first_line + (line - directive_line - 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(file: &str, line: u32, column: Option<u32>, severity: Severity, message: &str) -> Diagnostic {
+        Diagnostic { file: PathBuf::from(file), line, column, severity, message: String::from(message) }
+    }
+
+    #[test]
+    fn gcc_output() {
+        let output = "/home/user/Blink/Blink.ino: In function 'void loop()':\n\
+            /home/user/Blink/Blink.ino:9:1: error: expected ';' before '}' token\n \
+            }\n \
+            ^\n\
+            C:\\Users\\user\\Blink\\util.h:3: warning: \"LED\" redefined\n\
+            collect2: error: ld returned 1 exit status\n";
+
+        assert_eq!(diagnostics_from_gcc_output(output), [
+            diagnostic("/home/user/Blink/Blink.ino", 9, Some(1), Severity::Error, "expected ';' before '}' token"),
+            diagnostic("C:\\Users\\user\\Blink\\util.h", 3, None, Severity::Warning, "\"LED\" redefined"),
+        ]);
+    }
+
+    #[test]
+    fn fatal_error() {
+        let line = "/home/user/Blink/Blink.ino:1:10: fatal error: Servo.h: No such file or directory";
+
+        assert_eq!(diagnostic_from_gcc_line(line), Some(
+            diagnostic("/home/user/Blink/Blink.ino", 1, Some(10), Severity::Error, "Servo.h: No such file or directory"),
+        ));
+    }
+
+    #[test]
+    fn generated_source_location() {
+        let source = "#include <Arduino.h>\n\
+            #line 1 \"/home/user/Blink/Blink.ino\"\n\
+            void setup();\n\
+            #line 5 \"/home/user/Blink/Blink.ino\"\n\
+            void setup() {\n\
+            }\n";
+
+        assert_eq!(sketch_location(source, 6), Some((PathBuf::from("/home/user/Blink/Blink.ino"), 6)));
+        assert_eq!(sketch_location(source, 3), Some((PathBuf::from("/home/user/Blink/Blink.ino"), 1)));
+        assert_eq!(sketch_location(source, 1), None);
+    }
+}
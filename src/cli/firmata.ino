@@ -0,0 +1,163 @@
+/*
+ * A minimal Firmata firmware, which is flashed by `cli::flash_firmata` if the installed Firmata
+ * library does not contain the StandardFirmata example. It supports digital and analog I/O, PWM,
+ * and the queries performed by the `arduinors` handshake.
+ */
+
+#include <Firmata.h>
+
+byte reportingPorts[TOTAL_PORTS];
+byte previousPorts[TOTAL_PORTS];
+unsigned long previousSample;
+
+void setPinModeCallback(byte pin, int mode) {
+  if (!IS_PIN_DIGITAL(pin)) return;
+
+  switch (mode) {
+    case PIN_MODE_INPUT:
+      pinMode(PIN_TO_DIGITAL(pin), INPUT);
+      break;
+    case PIN_MODE_PULLUP:
+      pinMode(PIN_TO_DIGITAL(pin), INPUT_PULLUP);
+      break;
+    case PIN_MODE_OUTPUT:
+      pinMode(PIN_TO_DIGITAL(pin), OUTPUT);
+      break;
+    case PIN_MODE_PWM:
+      if (!IS_PIN_PWM(pin)) return;
+      pinMode(PIN_TO_PWM(pin), OUTPUT);
+      break;
+    case PIN_MODE_ANALOG:
+      if (!IS_PIN_ANALOG(pin)) return;
+      pinMode(PIN_TO_DIGITAL(pin), INPUT);
+      break;
+    default:
+      Firmata.sendString("Unknown pin mode");
+      return;
+  }
+
+  Firmata.setPinMode(pin, mode);
+  Firmata.setPinState(pin, 0);
+}
+
+void analogWriteCallback(byte pin, int value) {
+  if (pin >= TOTAL_PINS || Firmata.getPinMode(pin) != PIN_MODE_PWM) return;
+
+  analogWrite(PIN_TO_PWM(pin), value);
+  Firmata.setPinState(pin, value);
+}
+
+void digitalWriteCallback(byte port, int value) {
+  for (byte bit = 0; bit < 8; bit++) {
+    byte pin = port * 8 + bit;
+
+    if (pin < TOTAL_PINS && Firmata.getPinMode(pin) == PIN_MODE_OUTPUT) {
+      byte state = (value >> bit) & 1;
+      digitalWrite(PIN_TO_DIGITAL(pin), state);
+      Firmata.setPinState(pin, state);
+    }
+  }
+}
+
+void setPinValueCallback(byte pin, int value) {
+  if (pin >= TOTAL_PINS || Firmata.getPinMode(pin) != PIN_MODE_OUTPUT) return;
+
+  digitalWrite(PIN_TO_DIGITAL(pin), value);
+  Firmata.setPinState(pin, value);
+}
+
+void reportDigitalCallback(byte port, int value) {
+  if (port >= TOTAL_PORTS) return;
+
+  reportingPorts[port] = value;
+  if (value) Firmata.sendDigitalPort(port, readPort(port, 0xFF));
+}
+
+void reportAnalogCallback(byte channel, int value) {
+  for (byte pin = 0; pin < TOTAL_PINS; pin++) {
+    if (IS_PIN_ANALOG(pin) && PIN_TO_ANALOG(pin) == channel) {
+      Firmata.setPinState(pin, value ? 1 : 0);
+    }
+  }
+}
+
+void sysexCallback(byte command, byte argc, byte *argv) {
+  switch (command) {
+    case CAPABILITY_QUERY:
+      Firmata.write(START_SYSEX);
+      Firmata.write(CAPABILITY_RESPONSE);
+      for (byte pin = 0; pin < TOTAL_PINS; pin++) {
+        if (IS_PIN_DIGITAL(pin)) {
+          Firmata.write(PIN_MODE_INPUT); Firmata.write(1);
+          Firmata.write(PIN_MODE_PULLUP); Firmata.write(1);
+          Firmata.write(PIN_MODE_OUTPUT); Firmata.write(1);
+        }
+        if (IS_PIN_ANALOG(pin)) {
+          Firmata.write(PIN_MODE_ANALOG); Firmata.write(10);
+        }
+        if (IS_PIN_PWM(pin)) {
+          Firmata.write(PIN_MODE_PWM); Firmata.write(8);
+        }
+        Firmata.write(127);
+      }
+      Firmata.write(END_SYSEX);
+      break;
+    case ANALOG_MAPPING_QUERY:
+      Firmata.write(START_SYSEX);
+      Firmata.write(ANALOG_MAPPING_RESPONSE);
+      for (byte pin = 0; pin < TOTAL_PINS; pin++) {
+        Firmata.write(IS_PIN_ANALOG(pin) ? PIN_TO_ANALOG(pin) : 127);
+      }
+      Firmata.write(END_SYSEX);
+      break;
+    case PIN_STATE_QUERY:
+      if (argc < 1 || argv[0] >= TOTAL_PINS) return;
+      Firmata.write(START_SYSEX);
+      Firmata.write(PIN_STATE_RESPONSE);
+      Firmata.write(argv[0]);
+      Firmata.write(Firmata.getPinMode(argv[0]));
+      Firmata.write(Firmata.getPinState(argv[0]) & 0x7F);
+      Firmata.write(END_SYSEX);
+      break;
+  }
+}
+
+void setup() {
+  Firmata.setFirmwareVersion(FIRMATA_FIRMWARE_MAJOR_VERSION, FIRMATA_FIRMWARE_MINOR_VERSION);
+  Firmata.attach(ANALOG_MESSAGE, analogWriteCallback);
+  Firmata.attach(DIGITAL_MESSAGE, digitalWriteCallback);
+  Firmata.attach(REPORT_ANALOG, reportAnalogCallback);
+  Firmata.attach(REPORT_DIGITAL, reportDigitalCallback);
+  Firmata.attach(SET_PIN_MODE, setPinModeCallback);
+  Firmata.attach(SET_DIGITAL_PIN_VALUE, setPinValueCallback);
+  Firmata.attach(START_SYSEX, sysexCallback);
+
+  for (byte pin = 0; pin < TOTAL_PINS; pin++) {
+    setPinModeCallback(pin, IS_PIN_ANALOG(pin) ? PIN_MODE_ANALOG : PIN_MODE_OUTPUT);
+  }
+
+  Firmata.begin(57600);
+}
+
+void loop() {
+  for (byte port = 0; port < TOTAL_PORTS; port++) {
+    byte value = readPort(port, 0xFF);
+
+    if (reportingPorts[port] && value != previousPorts[port]) {
+      Firmata.sendDigitalPort(port, value);
+    }
+    previousPorts[port] = value;
+  }
+
+  while (Firmata.available()) Firmata.processInput();
+
+  if (millis() - previousSample >= 19) {
+    previousSample = millis();
+
+    for (byte pin = 0; pin < TOTAL_PINS; pin++) {
+      if (IS_PIN_ANALOG(pin) && Firmata.getPinMode(pin) == PIN_MODE_ANALOG && Firmata.getPinState(pin)) {
+        Firmata.sendAnalog(PIN_TO_ANALOG(pin), analogRead(PIN_TO_ANALOG(pin)));
+      }
+    }
+  }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Board;
+use super::Error;
+use super::client::{ArduinoCli, default_client};
+
+/// The name of the Arduino library providing Firmata.
+const FIRMATA_LIBRARY: &str = "Firmata";
+
+/// The name of the StandardFirmata example sketch, as contained in the Firmata library.
+const STANDARD_FIRMATA: &str = "StandardFirmata";
+
+/// The name of the sketch which is generated if the Firmata library does not contain
+/// StandardFirmata.
+const GENERATED_SKETCH: &str = "ArduinorsFirmata";
+
+/// The source of the generated sketch.
+const GENERATED_SKETCH_SOURCE: &str = include_str!("firmata.ino");
+
+/// Compiles StandardFirmata and uploads it onto the Arduino with the given board.
+///
+/// The board's core and the Firmata library are installed first, if they are missing. The
+/// StandardFirmata example of the Firmata library is used if it exists, otherwise a minimal
+/// Firmata sketch is generated in the system's temporary directory.
+///
+/// # Errors
+/// * `UnknownCore`, if the board has an unknown core.
+/// * `LibraryNotFound`, if the Firmata library can not be installed.
+/// * `Io`, if the sketch has to be generated, but can not be written.
+/// * as described for `cli::compile` and `cli::upload`.
+pub fn flash_firmata(board: &Board) -> Result<(), Error> {
+    default_client().flash_firmata(board)
+}
+
+impl ArduinoCli {
+
+    /// Compiles StandardFirmata and uploads it onto the Arduino with the given board, as with
+    /// `cli::flash_firmata`.
+    pub fn flash_firmata(&self, board: &Board) -> Result<(), Error> {
+        let core = board.core_id().ok_or(Error::UnknownCore)?;
+        self.install_core(core)?;
+
+        let sketch = self.firmata_sketch()?;
+        self.compile(&sketch, board)?;
+        self.upload(&sketch, board)
+    }
+
+    /// The path of a Firmata sketch, which is either the installed library's StandardFirmata
+    /// example or a generated sketch.
+    fn firmata_sketch(&self) -> Result<PathBuf, Error> {
+        let library_dir = match self.firmata_library_dir()? {
+            Some(directory) => directory,
+            None => {
+                self.install_library(FIRMATA_LIBRARY)?;
+                self.firmata_library_dir()?
+                    .ok_or_else(|| Error::LibraryNotFound(String::from(FIRMATA_LIBRARY)))?
+            }
+        };
+
+        let example = library_dir.join("examples").join(STANDARD_FIRMATA);
+
+        if example.join(format!("{}.ino", STANDARD_FIRMATA)).is_file() {
+            Ok(example)
+        } else {
+            generate_sketch(&std::env::temp_dir().join("arduinors"))
+        }
+    }
+
+    /// The installation directory of the Firmata library, if it is installed.
+    fn firmata_library_dir(&self) -> Result<Option<PathBuf>, Error> {
+        let libraries = self.library_list_installed()?;
+
+        Ok(libraries
+            .into_iter()
+            .find(|library| library.name() == FIRMATA_LIBRARY)
+            .map(|library| library.install_dir().to_path_buf()))
+    }
+}
+
+/// Writes the generated Firmata sketch into the given directory, returning the sketch's path.
+fn generate_sketch(directory: &Path) -> Result<PathBuf, Error> {
+    let sketch = directory.join(GENERATED_SKETCH);
+
+    fs::create_dir_all(&sketch)
+        .and_then(|_| fs::write(sketch.join(format!("{}.ino", GENERATED_SKETCH)), GENERATED_SKETCH_SOURCE))
+        .map_err(|error| Error::Io(format!("failed to generate a Firmata sketch: {}", error)))?;
+
+    Ok(sketch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_sketch() {
+        let directory = std::env::temp_dir().join(format!("arduinors-test-{}", std::process::id()));
+
+        let sketch = generate_sketch(&directory).unwrap();
+        let source = fs::read_to_string(sketch.join("ArduinorsFirmata.ino")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(source.contains("#include <Firmata.h>"));
+    }
+}
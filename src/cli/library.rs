@@ -0,0 +1,162 @@
+use std::str;
+use std::process;
+use std::process::Command;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use serde_json as json;
+
+use super::Error;
+use super::client::{ArduinoCli, default_client};
+use super::version::{Version, WRAPPED_OUTPUT_VERSION};
+
+/// A wrapper for the result of calling `arduino-cli lib list --format json`.
+///
+/// Versions of the Arduino CLI before 0.36 print a bare (possibly `null`) list of entries instead.
+#[derive(Serialize, Deserialize)]
+struct LibraryList {
+    #[serde(default)]
+    installed_libraries: Vec<LibraryEntry>,
+}
+
+/// An entry in the list of installed libraries, which also contains release information that is
+/// not of interest.
+#[derive(Serialize, Deserialize)]
+struct LibraryEntry {
+    library: Library,
+}
+
+/// A library installed for use by the Arduino CLI, as listed by `arduino-cli lib list`.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Library {
+    name: String,
+    version: String,
+    install_dir: PathBuf,
+}
+
+impl Library {
+
+    pub fn name(&self) -> &str { &self.name }
+
+    pub fn version(&self) -> &str { &self.version }
+
+    /// The directory containing the library's sources and examples.
+    pub fn install_dir(&self) -> &Path { &self.install_dir }
+}
+
+/// Installs the latest version of the library with the given name.
+///
+/// # Errors
+/// * `CommandFailure`, if the `arduino-cli` command fails, e.g. because there is no such library.
+pub fn install_library(name: &str) -> Result<(), Error> {
+    default_client().install_library(name)
+}
+
+/// Calls `arduino-cli lib list` and converts the resulting entries into `Library` instances.
+///
+/// # Errors
+/// * `SpawnFailure` or `CommandFailure`, if the `arduino-cli` command can not be run or fails.
+/// * `UnknownFormat`, if the call to the Arduino CLI produced an output in a different format
+///   than expected.
+pub fn library_list_installed() -> Result<Vec<Library>, Error> {
+    default_client().library_list_installed()
+}
+
+impl ArduinoCli {
+
+    /// Installs the library with the given name, as with `cli::install_library`.
+    pub fn install_library(&self, name: &str) -> Result<(), Error> {
+        self.detected_version()?;
+        self.run(install_library_command(self, name)).map(|_| ())
+    }
+
+    /// Lists the installed libraries, as with `cli::library_list_installed`.
+    pub fn library_list_installed(&self) -> Result<Vec<Library>, Error> {
+        let version = self.detected_version()?;
+        libraries_from_output(self.run(library_list_command(self)), version)
+    }
+}
+
+/// The command which asks the Arduino CLI to install the library with the given name.
+pub(super) fn install_library_command(cli: &ArduinoCli, name: &str) -> Command {
+    cli.command(["lib", "install", name])
+}
+
+/// The command which asks the Arduino CLI for a list of installed libraries in JSON format.
+pub(super) fn library_list_command(cli: &ArduinoCli) -> Command {
+    cli.command(["lib", "list", "--format", "json"])
+}
+
+/// Converts the output of the library list command into library instances.
+pub(super) fn libraries_from_output(output: Result<process::Output, Error>, version: Version) -> Result<Vec<Library>, Error> {
+    // The command line output has to be converted to a valid UTF-8 string before being able to
+    // use it.
+    str::from_utf8(&output?.stdout)
+        .map_err(|_| Error::UnknownFormat)
+        .and_then(|library_json| libraries_from_json(library_json, version))
+}
+
+/// Converts a given output from `arduino-cli lib list --format json` into library instances,
+/// using the format printed by the given version of the Arduino CLI.
+fn libraries_from_json(library_json: &str, version: Version) -> Result<Vec<Library>, Error> {
+    let entries = if version >= WRAPPED_OUTPUT_VERSION {
+        json::from_str(library_json).map(|list: LibraryList| list.installed_libraries)
+    } else {
+        json::from_str(library_json).map(|entries: Option<Vec<LibraryEntry>>| entries.unwrap_or_default())
+    };
+
+    entries
+        .map(|entries| entries.into_iter().map(|entry| entry.library).collect())
+        .map_err(|_| Error::UnknownFormat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firmata() -> Library {
+        Library {
+            name: String::from("Firmata"),
+            version: String::from("2.5.9"),
+            install_dir: PathBuf::from("/home/user/Arduino/libraries/Firmata"),
+        }
+    }
+
+    #[test]
+    fn wrapped_list() {
+        let library_json = r#"{"installed_libraries": [{"library": {
+            "name": "Firmata", "version": "2.5.9", "location": "user",
+            "install_dir": "/home/user/Arduino/libraries/Firmata"
+        }}]}"#;
+
+        let result = libraries_from_json(library_json, Version::new(1, 0, 4)).unwrap();
+
+        assert_eq!(result, vec![firmata()]);
+    }
+
+    #[test]
+    fn bare_list() {
+        let library_json = r#"[{"library": {
+            "name": "Firmata", "version": "2.5.9",
+            "install_dir": "/home/user/Arduino/libraries/Firmata"
+        }, "release": {"version": "2.5.9"}}]"#;
+
+        let result = libraries_from_json(library_json, Version::new(0, 21, 1)).unwrap();
+
+        assert_eq!(result, vec![firmata()]);
+    }
+
+    #[test]
+    fn no_libraries() {
+        assert!(libraries_from_json("null", Version::new(0, 21, 1)).unwrap().is_empty());
+        assert!(libraries_from_json("{}", Version::new(1, 0, 4)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_json() {
+        let malformed_json = r#"[{"library": {"name": "Firmata"}}]"#;
+
+        let err = libraries_from_json(malformed_json, Version::new(0, 21, 1)).unwrap_err();
+
+        assert_eq!(err, Error::UnknownFormat);
+    }
+}
@@ -1,4 +1,14 @@
 //! This module provides an interface for interacting with the Arduino CLI.
+//!
+//! The version of the installed Arduino CLI is detected once, and determines the flags passed to
+//! it and the formats expected of its output. Any function other than `version` fails with
+//! `Error::UnsupportedVersion` if the version is not supported.
+
+mod client;
+pub use client::{ArduinoCli, Runner, ProcessRunner, FakeRunner};
+
+mod version;
+pub use version::{version, Version, MINIMUM_SUPPORTED_VERSION};
 
 mod run;
 pub use run::*;
@@ -7,11 +17,136 @@ mod board;
 pub use board::*;
 
 mod core;
+pub use self::core::{Core, install_core, install_core_streaming, update_core_index, core_list_all};
+
+mod library;
+pub use library::*;
+
+mod diagnostic;
+pub use diagnostic::{Diagnostic, Severity};
+
+mod options;
+pub use options::*;
+
+mod stream;
+pub use stream::{Stream, OutputEvent, Activity};
+
+mod cancellation;
+pub use cancellation::CancellationToken;
+
+mod firmata;
+pub use firmata::*;
+
+#[cfg(feature = "async")]
+pub mod nonblocking;
+
+use std::fmt;
 
 /// The kinds of errors that can occur as a result of interacting with the Arduino CLI.
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Error {
-    CommandFailure,
+    /// The Arduino CLI could not be run, e.g. because its binary does not exist.
+    SpawnFailure { command: String, message: String },
+    /// The Arduino CLI exited with a non-zero status.
+    CommandFailure(FailedCommand),
+    /// The compiler reported errors in a sketch, which are contained in its diagnostics.
+    CompilationFailure { failure: FailedCommand, diagnostics: Vec<Diagnostic> },
+    /// The Arduino CLI had to be stopped (e.g. because of a timeout), but could not be killed along
+    /// with the processes it started, which may still be running.
+    KillFailure { command: String, message: String },
+    /// The Arduino CLI did not finish in time, and was killed.
+    Timeout(FailedCommand),
+    /// The Arduino CLI was stopped before it finished.
+    Cancelled(FailedCommand),
     UnknownFormat,
     InvalidSketchPath,
- }
+    /// A board's core is unknown, so there is no FQBN to pass to the Arduino CLI.
+    UnknownCore,
+    /// The library with the given name is not installed, even after installing it.
+    LibraryNotFound(String),
+    /// An I/O operation outside of the Arduino CLI failed, e.g. writing a sketch.
+    Io(String),
+    /// The installed Arduino CLI has the given version, which is not supported (see
+    /// `Version::is_supported`).
+    UnsupportedVersion(Version),
+    /// The compiled sketch uses more bytes of the given section than its size budget allows.
+    SizeBudgetExceeded { section: String, used: u64, limit: u64 },
+    /// The Arduino CLI did not report the compiled sketch's usage of the given section, which is
+    /// limited by its size budget.
+    SizeNotReported(String),
+}
+
+impl fmt::Display for Error {
+
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SpawnFailure { command, message } =>
+                write!(formatter, "failed to run `{}`: {}", command, message),
+            Error::CommandFailure(failure) => {
+                match failure.exit_code() {
+                    Some(code) => write!(formatter, "`{}` exited with status {}", failure.command(), code)?,
+                    None => write!(formatter, "`{}` was terminated by a signal", failure.command())?,
+                }
+
+                match failure.stderr().trim() {
+                    "" => Ok(()),
+                    stderr => write!(formatter, ":\n{}", stderr),
+                }
+            },
+            Error::CompilationFailure { failure, diagnostics } => {
+                write!(formatter, "`{}` failed to compile the sketch:", failure.command())?;
+
+                for diagnostic in diagnostics.iter().filter(|diagnostic| diagnostic.severity() == Severity::Error) {
+                    write!(formatter, "\n{}", diagnostic)?;
+                }
+
+                Ok(())
+            },
+            Error::KillFailure { command, message } =>
+                write!(formatter, "failed to stop `{}`, which may still be running: {}", command, message),
+            Error::Timeout(failure) => write!(formatter, "`{}` timed out", failure.command()),
+            Error::Cancelled(failure) => write!(formatter, "`{}` was cancelled", failure.command()),
+            Error::UnknownFormat => write!(formatter, "the Arduino CLI printed output in an unknown format"),
+            Error::InvalidSketchPath => write!(formatter, "the path is not an Arduino sketch directory"),
+            Error::UnknownCore => write!(formatter, "the board's core is unknown"),
+            Error::LibraryNotFound(name) => write!(formatter, "the library `{}` is not installed", name),
+            Error::Io(message) => write!(formatter, "{}", message),
+            Error::UnsupportedVersion(version) =>
+                write!(formatter, "arduino-cli {} is not supported (at least {} is required)", version, MINIMUM_SUPPORTED_VERSION),
+            Error::SizeBudgetExceeded { section, used, limit } =>
+                write!(formatter, "the sketch uses {} bytes of `{}`, exceeding its budget of {} bytes", used, section, limit),
+            Error::SizeNotReported(section) =>
+                write!(formatter, "the Arduino CLI did not report the sketch's usage of `{}`, which has a size budget", section),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A run of the Arduino CLI which did not succeed, along with everything it printed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FailedCommand {
+    pub(crate) command: String,
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+}
+
+impl FailedCommand {
+
+    /// The command line which was run, e.g. `arduino-cli compile --fqbn arduino:avr:uno sketch`.
+    pub fn command(&self) -> &str { &self.command }
+
+    /// The exit code of the Arduino CLI, or `None` if it was terminated by a signal (or killed
+    /// after a timeout).
+    pub fn exit_code(&self) -> Option<i32> { self.exit_code }
+
+    pub fn stdout(&self) -> &str { &self.stdout }
+
+    pub fn stderr(&self) -> &str { &self.stderr }
+
+    /// A failure of the given command line, which was stopped before it printed anything.
+    pub(crate) fn stopped(command: String) -> FailedCommand {
+        FailedCommand { command, exit_code: None, stdout: String::new(), stderr: String::new() }
+    }
+}
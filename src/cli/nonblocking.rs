@@ -0,0 +1,250 @@
+//! This module provides async counterparts of the `cli` functions, which run the Arduino CLI via
+//! `tokio::process` instead of blocking the calling thread.
+//!
+//! The commands are built by the default `ArduinoCli` client (see `ArduinoCli::set_default`) and
+//! are stopped according to its timeout and cancellation token, but are always spawned as
+//! processes rather than run by the client's runner. Dropping a future kills its command along
+//! with the processes it started.
+//!
+//! The module is only available with the `async` feature enabled.
+
+use std::io;
+use std::path::Path;
+use std::process::{self, Output};
+
+use tokio::process::Command;
+use tokio::time;
+
+use crate::Board;
+use super::{ArduinoCli, Core, Library, NetworkBoard, ListedBoard, Target, Version, Diagnostic, CompileOptions, CompileOutput, UploadOptions, Error, FailedCommand};
+use super::cancellation::{Limits, Watch};
+use super::client::{default_client, command_line, check_output};
+use super::stream::{IDLE_INTERVAL, lead_process_group, kill_process_tree};
+use super::version::{version_command, version_from_output, check_supported};
+use super::run::{compile_command, compile_output_from_output, upload_command, burn_bootloader_command};
+use super::board::{board_list_command, board_list_from_output, listed_boards};
+use super::core::{install_core_command, update_core_index_command, core_list_all_command, cores_from_output};
+use super::library::{install_library_command, library_list_command, libraries_from_output};
+
+/// Calls `arduino-cli version` and parses the resulting version, as with `cli::version`.
+pub async fn version() -> Result<Version, Error> {
+    let client = default_client();
+    version_from_output(run(client, version_command(client)).await)
+}
+
+/// Spawns the given command built by the given client and waits for its output, which is only
+/// successful if the command succeeded. The command is killed along with the processes it started
+/// if the client's limits say so.
+async fn run(client: &ArduinoCli, command: process::Command) -> Result<Output, Error> {
+    run_limited(client, command, &Limits::default()).await
+}
+
+/// Spawns the given command like `run`, but also kills it if the given limits of the call say so.
+/// The call's timeout takes precedence over the client's. The output of a stopped command is not
+/// captured.
+async fn run_limited(client: &ArduinoCli, mut command: process::Command, limits: &Limits) -> Result<Output, Error> {
+    let command_line = command_line(&command);
+    let all_limits = [limits, &client.limits];
+    let watch = Watch::start(&all_limits);
+
+    // A command is not started if it has been cancelled already.
+    if let Some(error) = watch.exceeded() { return Err(error(FailedCommand::stopped(command_line))); }
+
+    lead_process_group(&mut command);
+    let child = match Command::from(command).kill_on_drop(true).spawn() {
+        Ok(child) => child,
+        Err(error) => return check_output(command_line, Err(error)),
+    };
+    let group = ProcessGroupGuard { id: child.id() };
+
+    let exceeded = async {
+        if !watch.is_limited() { return std::future::pending().await; }
+
+        loop {
+            if let Some(error) = watch.exceeded() { return error; }
+            time::sleep(IDLE_INTERVAL).await;
+        }
+    };
+
+    tokio::select! {
+        output = child.wait_with_output() => {
+            group.disarm();
+            check_output(command_line, output)
+        },
+        error = exceeded => {
+            // The child itself is killed once its output is dropped.
+            match group.kill() {
+                Ok(()) => Err(error(FailedCommand::stopped(command_line))),
+                Err(kill_error) => Err(Error::KillFailure { command: command_line, message: kill_error.to_string() }),
+            }
+        },
+    }
+}
+
+/// Kills the process group led by a spawned command when dropped, i.e. when the future waiting for
+/// the command is dropped before it has finished.
+struct ProcessGroupGuard {
+    id: Option<u32>,
+}
+
+impl ProcessGroupGuard {
+
+    /// Kills the process group right away, instead of when the guard is dropped.
+    fn kill(mut self) -> io::Result<()> {
+        self.id.take().map_or(Ok(()), |id| kill_process_tree(id, || Ok(())))
+    }
+
+    /// Keeps the process group alive, as its command has finished.
+    fn disarm(mut self) {
+        self.id = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+
+    fn drop(&mut self) {
+        // Errors can not be reported from here, so the processes may keep running.
+        if let Some(id) = self.id.take() { let _ = kill_process_tree(id, || Ok(())); }
+    }
+}
+
+/// The version of the installed Arduino CLI, which is only detected if it has not been yet.
+async fn detected_version() -> Result<Version, Error> {
+    let client = default_client();
+
+    match client.cached_version() {
+        Some(version) => check_supported(version),
+        None => check_supported(client.cache_version(version().await?)),
+    }
+}
+
+/// Compiles a sketch at a given path, for a given board, as with `cli::compile`.
+pub async fn compile<T: Target + ?Sized>(sketch: &Path, board: &T) -> Result<Vec<Diagnostic>, Error> {
+    compile_with(sketch, board, &CompileOptions::new()).await.map(|output| output.diagnostics().to_vec())
+}
+
+/// Compiles a sketch at a given path, for a given board, with the given options, as with
+/// `cli::compile_with`.
+pub async fn compile_with<T: Target + ?Sized>(sketch: &Path, board: &T, options: &CompileOptions) -> Result<CompileOutput, Error> {
+    let client = default_client();
+    detected_version().await?;
+    let output = run_limited(client, compile_command(client, sketch, board, options, true)?, &options.limits).await;
+    compile_output_from_output(output, sketch, board, options)
+}
+
+/// Uploads a **compiled** sketch onto Arduino with the given board, as with `cli::upload`.
+pub async fn upload<T: Target + ?Sized>(sketch: &Path, board: &T) -> Result<(), Error> {
+    upload_with(board, &UploadOptions::new().sketch(sketch)).await
+}
+
+/// Uploads onto Arduino with the given board, with the given options, as with `cli::upload_with`.
+pub async fn upload_with<T: Target + ?Sized>(board: &T, options: &UploadOptions) -> Result<(), Error> {
+    let client = default_client();
+    let version = detected_version().await?;
+    run_limited(client, upload_command(client, board, options, version)?, &options.limits).await.map(|_| ())
+}
+
+/// Burns the bootloader onto the Arduino with the given board, as with `cli::burn_bootloader`.
+pub async fn burn_bootloader<T: Target + ?Sized>(board: &T, programmer: &str) -> Result<(), Error> {
+    let client = default_client();
+    let version = detected_version().await?;
+    run(client, burn_bootloader_command(client, board, programmer, version)?).await.map(|_| ())
+}
+
+/// Lists the connected serial boards, as with `cli::board_list_serial`.
+pub async fn board_list_serial() -> Result<Vec<Board>, Error> {
+    board_list().await.map(|boards| boards.into_iter().filter_map(ListedBoard::serial).collect())
+}
+
+/// Lists the network boards, as with `cli::board_list_network`.
+pub async fn board_list_network() -> Result<Vec<NetworkBoard>, Error> {
+    board_list().await.map(|boards| boards.into_iter().filter_map(ListedBoard::network).collect())
+}
+
+/// Lists the serial and network boards, as with `cli::board_list`.
+pub async fn board_list() -> Result<Vec<ListedBoard>, Error> {
+    let client = default_client();
+    let version = detected_version().await?;
+    board_list_from_output(run(client, board_list_command(client)).await, version).map(listed_boards)
+}
+
+/// Installs the core with the given ID, as with `cli::install_core`.
+pub async fn install_core(id: &str) -> Result<(), Error> {
+    let client = default_client();
+    detected_version().await?;
+    run(client, install_core_command(client, id)).await.map(|_| ())
+}
+
+/// Updates the Arduino CLI's index of cores, as with `cli::update_core_index`.
+pub async fn update_core_index() -> Result<(), Error> {
+    let client = default_client();
+    detected_version().await?;
+
+    // Failing to update the index is not an error, as the previous index remains usable.
+    match run(client, update_core_index_command(client)).await {
+        Ok(_) | Err(Error::CommandFailure(_)) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Lists all Arduino cores, as with `cli::core_list_all`.
+pub async fn core_list_all() -> Result<Vec<Core>, Error> {
+    let client = default_client();
+    let version = detected_version().await?;
+    cores_from_output(run(client, core_list_all_command(client, version)).await, version)
+}
+
+/// Installs the library with the given name, as with `cli::install_library`.
+pub async fn install_library(name: &str) -> Result<(), Error> {
+    let client = default_client();
+    detected_version().await?;
+    run(client, install_library_command(client, name)).await.map(|_| ())
+}
+
+/// Lists the installed libraries, as with `cli::library_list_installed`.
+pub async fn library_list_installed() -> Result<Vec<Library>, Error> {
+    let client = default_client();
+    let version = detected_version().await?;
+    libraries_from_output(run(client, library_list_command(client)).await, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_timeout() {
+        let cli = ArduinoCli::new().binary("sh").timeout(Duration::from_millis(100));
+
+        let result = run(&cli, cli.command(["-c", "sleep 10"])).await;
+
+        assert!(matches!(result, Err(Error::Timeout(_))));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn call_timeout_precedence() {
+        let cli = ArduinoCli::new().binary("sh").timeout(Duration::from_millis(100));
+        let limits = Limits { timeout: Some(Duration::from_secs(10)), cancellation: None };
+
+        assert!(run_limited(&cli, cli.command(["-c", "sleep 0.3"]), &limits).await.is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dropped_process_tree() {
+        let cli = ArduinoCli::new().binary("sh");
+        let marker = std::env::temp_dir().join(format!("dropped{}", std::process::id()));
+        let script = format!("(sleep 0.3; touch '{}') & sleep 10", marker.display());
+
+        // Dropping the future once the timeout elapses also kills the background process.
+        let future = run(&cli, cli.command(["-c", script.as_str()]));
+        let result = time::timeout(Duration::from_millis(100), future).await;
+        time::sleep(Duration::from_millis(500)).await;
+
+        assert!(result.is_err());
+        assert!(!marker.exists());
+    }
+}
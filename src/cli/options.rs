@@ -0,0 +1,372 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{SizeBudget, CancellationToken};
+use super::cancellation::Limits;
+use super::version::{Version, PLUGGABLE_DISCOVERY_VERSION};
+
+/// The level of compiler warnings, as passed via `--warnings`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Warnings {
+    None,
+    Default,
+    More,
+    All,
+}
+
+impl Warnings {
+
+    fn as_arg(&self) -> &'static str {
+        match self {
+            Warnings::None => "none",
+            Warnings::Default => "default",
+            Warnings::More => "more",
+            Warnings::All => "all",
+        }
+    }
+}
+
+/// The options of a compilation, which are passed to `arduino-cli compile` as flags.
+///
+/// Options which are not set are left to the Arduino CLI's configuration:
+///
+/// ```no_run
+/// use arduinors::cli::{self, CompileOptions, Warnings};
+/// # let sketch = std::path::Path::new("Blink");
+/// # let board = &cli::board_list_serial().unwrap()[0];
+///
+/// let options = CompileOptions::new()
+///     .build_property("build.extra_flags", "-DDEBUG")
+///     .warnings(Warnings::All)
+///     .output_dir("target/firmware");
+///
+/// let output = cli::compile_with(sketch, board, &options)?;
+/// # Ok::<(), cli::Error>(())
+/// ```
+///
+/// Flags which are not supported by the installed version of the Arduino CLI make it fail.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct CompileOptions {
+    build_properties: Vec<String>,
+    pub(super) output_dir: Option<PathBuf>,
+    pub(super) export_binaries: bool,
+    libraries: Vec<PathBuf>,
+    warnings: Option<Warnings>,
+    optimize_for_debug: bool,
+    pub(super) build_path: Option<PathBuf>,
+    clean: bool,
+    pub(super) size_budget: Option<SizeBudget>,
+    pub(super) limits: Limits,
+}
+
+impl CompileOptions {
+
+    pub fn new() -> CompileOptions { CompileOptions::default() }
+
+    /// Overrides a build property of the board's platform (`--build-property`).
+    pub fn build_property(mut self, key: &str, value: &str) -> CompileOptions {
+        self.build_properties.push(format!("{}={}", key, value));
+        self
+    }
+
+    /// Sets the directory into which the compiled binaries are copied (`--output-dir`).
+    pub fn output_dir<P: Into<PathBuf>>(mut self, path: P) -> CompileOptions {
+        self.output_dir = Some(path.into());
+        self
+    }
+
+    /// Sets whether the compiled binaries are copied into the sketch's `build` directory
+    /// (`--export-binaries`).
+    pub fn export_binaries(mut self, export: bool) -> CompileOptions {
+        self.export_binaries = export;
+        self
+    }
+
+    /// Adds a directory containing libraries, which take precedence over installed libraries
+    /// (`--libraries`).
+    pub fn library_dir<P: Into<PathBuf>>(mut self, path: P) -> CompileOptions {
+        self.libraries.push(path.into());
+        self
+    }
+
+    /// Sets the level of compiler warnings (`--warnings`).
+    pub fn warnings(mut self, warnings: Warnings) -> CompileOptions {
+        self.warnings = Some(warnings);
+        self
+    }
+
+    /// Sets whether the sketch is compiled for debugging rather than release
+    /// (`--optimize-for-debug`).
+    pub fn optimize_for_debug(mut self, optimize: bool) -> CompileOptions {
+        self.optimize_for_debug = optimize;
+        self
+    }
+
+    /// Sets the directory in which the sketch is built (`--build-path`).
+    pub fn build_path<P: Into<PathBuf>>(mut self, path: P) -> CompileOptions {
+        self.build_path = Some(path.into());
+        self
+    }
+
+    /// Sets whether the build path is cleaned and the cached core is not used (`--clean`).
+    pub fn clean(mut self, clean: bool) -> CompileOptions {
+        self.clean = clean;
+        self
+    }
+
+    /// Sets limits of the memory usage of the compiled sketch, which fail the compilation if
+    /// they are exceeded, or if the Arduino CLI does not report the usage of a limited section.
+    pub fn size_budget(mut self, budget: SizeBudget) -> CompileOptions {
+        self.size_budget = Some(budget);
+        self
+    }
+
+    /// Sets how long compilation may take, overriding the client's timeout.
+    pub fn timeout(mut self, timeout: Duration) -> CompileOptions {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a token which stops compilation when it is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> CompileOptions {
+        self.limits.cancellation = Some(token);
+        self
+    }
+
+    /// The flags passed to `arduino-cli compile` for these options.
+    pub(super) fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![];
+
+        for property in &self.build_properties {
+            args.extend([OsString::from("--build-property"), OsString::from(property)]);
+        }
+
+        if let Some(output_dir) = &self.output_dir {
+            args.extend([OsString::from("--output-dir"), output_dir.into()]);
+        }
+
+        if self.export_binaries { args.push(OsString::from("--export-binaries")); }
+
+        for library in &self.libraries {
+            args.extend([OsString::from("--libraries"), library.into()]);
+        }
+
+        if let Some(warnings) = self.warnings {
+            args.extend([OsString::from("--warnings"), OsString::from(warnings.as_arg())]);
+        }
+
+        if self.optimize_for_debug { args.push(OsString::from("--optimize-for-debug")); }
+
+        if let Some(build_path) = &self.build_path {
+            args.extend([OsString::from("--build-path"), build_path.into()]);
+        }
+
+        if self.clean { args.push(OsString::from("--clean")); }
+
+        args
+    }
+}
+
+/// The options of an upload, which are passed to `arduino-cli upload` as flags.
+///
+/// Either a compiled sketch or prebuilt binaries have to be uploaded:
+///
+/// ```no_run
+/// use arduinors::cli::{self, UploadOptions};
+/// # let board = &cli::board_list_serial().unwrap()[0];
+///
+/// let options = UploadOptions::new()
+///     .input_file("releases/Blink-1.2.0.hex")
+///     .verify(true);
+///
+/// cli::upload_with(board, &options)?;
+/// # Ok::<(), cli::Error>(())
+/// ```
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct UploadOptions {
+    pub(super) sketch: Option<PathBuf>,
+    programmer: Option<String>,
+    verify: bool,
+    input_dir: Option<PathBuf>,
+    input_file: Option<PathBuf>,
+    discovery_timeout: Option<Duration>,
+    pub(super) limits: Limits,
+}
+
+impl UploadOptions {
+
+    pub fn new() -> UploadOptions { UploadOptions::default() }
+
+    /// Sets the **compiled** sketch which is uploaded. The path should point to the sketch
+    /// **directory**, not **file**.
+    pub fn sketch<P: Into<PathBuf>>(mut self, path: P) -> UploadOptions {
+        self.sketch = Some(path.into());
+        self
+    }
+
+    /// Sets the programmer used for uploading instead of the board's bootloader (`--programmer`).
+    pub fn programmer(mut self, programmer: &str) -> UploadOptions {
+        self.programmer = Some(String::from(programmer));
+        self
+    }
+
+    /// Sets whether the uploaded binary is verified after uploading (`--verify`).
+    pub fn verify(mut self, verify: bool) -> UploadOptions {
+        self.verify = verify;
+        self
+    }
+
+    /// Sets the directory containing the binaries to upload (`--input-dir`), e.g. an output
+    /// directory of a compilation. The binaries are looked up by the sketch's name, if a sketch is
+    /// set.
+    pub fn input_dir<P: Into<PathBuf>>(mut self, path: P) -> UploadOptions {
+        self.input_dir = Some(path.into());
+        self
+    }
+
+    /// Sets the binary to upload (`--input-file`), which does not require a sketch.
+    pub fn input_file<P: Into<PathBuf>>(mut self, path: P) -> UploadOptions {
+        self.input_file = Some(path.into());
+        self
+    }
+
+    /// Sets how long the Arduino CLI waits for ports to be discovered (`--discovery-timeout`).
+    ///
+    /// Versions of the Arduino CLI before 0.19 do not discover ports, so the timeout is not
+    /// passed to them.
+    pub fn discovery_timeout(mut self, timeout: Duration) -> UploadOptions {
+        self.discovery_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long uploading may take, overriding the client's timeout. A hung upload (e.g.
+    /// onto a wrong port) is killed along with the upload tool once the timeout passes.
+    pub fn timeout(mut self, timeout: Duration) -> UploadOptions {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a token which stops uploading when it is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> UploadOptions {
+        self.limits.cancellation = Some(token);
+        self
+    }
+
+    /// Indicates whether the options specify something to upload.
+    pub(super) fn has_input(&self) -> bool {
+        self.sketch.is_some() || self.input_dir.is_some() || self.input_file.is_some()
+    }
+
+    /// The flags passed to `arduino-cli upload` for these options and the given version of the
+    /// Arduino CLI.
+    pub(super) fn args(&self, version: Version) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![];
+
+        if let Some(programmer) = &self.programmer {
+            args.extend([OsString::from("--programmer"), OsString::from(programmer)]);
+        }
+
+        if self.verify { args.push(OsString::from("--verify")); }
+
+        if let Some(input_dir) = &self.input_dir {
+            args.extend([OsString::from("--input-dir"), input_dir.into()]);
+        }
+
+        if let Some(input_file) = &self.input_file {
+            args.extend([OsString::from("--input-file"), input_file.into()]);
+        }
+
+        if let Some(timeout) = self.discovery_timeout.filter(|_| version >= PLUGGABLE_DISCOVERY_VERSION) {
+            args.extend([OsString::from("--discovery-timeout"), OsString::from(format!("{}ms", timeout.as_millis()))]);
+        }
+
+        args
+    }
+}
+
+/// The options of a core installation via `cli::install_core_streaming`.
+///
+/// Installing a core downloads its tools, which can take minutes, so it can be stopped from
+/// another thread:
+///
+/// ```no_run
+/// use std::ops::ControlFlow;
+/// use arduinors::cli::{self, CancellationToken, InstallOptions};
+///
+/// let token = CancellationToken::new();
+/// let options = InstallOptions::new().cancellation(token.clone());
+///
+/// // The token can be cancelled from e.g. a GUI's cancel button.
+/// cli::install_core_streaming("arduino:avr", &options, |event| {
+///     if let Some(percentage) = event.percentage() { println!("{:.0}%", percentage); }
+///     ControlFlow::Continue(())
+/// })?;
+/// # Ok::<(), cli::Error>(())
+/// ```
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct InstallOptions {
+    pub(super) limits: Limits,
+}
+
+impl InstallOptions {
+
+    pub fn new() -> InstallOptions { InstallOptions::default() }
+
+    /// Sets how long installation may take, overriding the client's timeout.
+    pub fn timeout(mut self, timeout: Duration) -> InstallOptions {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a token which stops installation when it is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> InstallOptions {
+        self.limits.cancellation = Some(token);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_args() {
+        let options = CompileOptions::new()
+            .build_property("build.extra_flags", "-DDEBUG -DLEVEL=2")
+            .build_property("compiler.cpp.extra_flags", "-Os")
+            .output_dir("out")
+            .library_dir("vendor")
+            .warnings(Warnings::More)
+            .clean(true);
+
+        assert_eq!(options.args(), [
+            "--build-property", "build.extra_flags=-DDEBUG -DLEVEL=2",
+            "--build-property", "compiler.cpp.extra_flags=-Os",
+            "--output-dir", "out",
+            "--libraries", "vendor",
+            "--warnings", "more",
+            "--clean",
+        ]);
+    }
+
+    #[test]
+    fn no_options() {
+        assert!(CompileOptions::new().args().is_empty());
+        assert!(UploadOptions::new().args(Version::new(1, 0, 4)).is_empty());
+    }
+
+    #[test]
+    fn upload_args() {
+        let options = UploadOptions::new()
+            .programmer("usbasp")
+            .verify(true)
+            .input_file("Blink.ino.hex")
+            .discovery_timeout(Duration::from_secs(5));
+
+        assert_eq!(options.args(Version::new(1, 0, 4)), [
+            "--programmer", "usbasp", "--verify", "--input-file", "Blink.ino.hex", "--discovery-timeout", "5000ms",
+        ]);
+        assert_eq!(options.args(Version::new(0, 18, 3)).len(), 5);
+    }
+}
@@ -1,61 +1,513 @@
 use std::process;
-use std::path::Path;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
 use std::fs;
+use serde::Deserialize;
+use serde_json as json;
 
-use crate::Board;
-use super::Error;
+use super::{Error, Target, Diagnostic, CompileOptions, UploadOptions, OutputEvent};
+use super::client::{ArduinoCli, default_client};
+use super::diagnostic::{DiagnosticFormat, diagnostics_from_formats, diagnostics_from_gcc_output, map_to_sketch};
+use super::version::{Version, PLUGGABLE_DISCOVERY_VERSION};
 
-/// Compiles a sketch at a given path, for a given board.
+/// A wrapper for the result of calling `arduino-cli compile --format json`.
+///
+/// Versions of the Arduino CLI before 0.33 do not print diagnostics, so they are parsed from the
+/// compiler's output instead.
+#[derive(Deserialize)]
+struct CompileResult {
+    #[serde(default)]
+    compiler_err: String,
+    builder_result: Option<BuilderResult>,
+}
+
+/// The part of a compile result describing the build.
+#[derive(Deserialize)]
+struct BuilderResult {
+    build_path: Option<PathBuf>,
+    diagnostics: Option<Vec<DiagnosticFormat>>,
+    executable_sections_size: Option<Vec<SectionFormat>>,
+}
+
+/// The size of a section of the compiled binary, as printed by `arduino-cli compile --format
+/// json`. A maximum size of 0 indicates that the maximum is unknown.
+#[derive(Deserialize)]
+struct SectionFormat {
+    name: String,
+    size: u64,
+    #[serde(default)]
+    max_size: u64,
+}
+
+/// The name of the section which is stored in flash memory (program storage space).
+const FLASH_SECTION: &str = "text";
+
+/// The name of the section which is stored in RAM (dynamic memory).
+const RAM_SECTION: &str = "data";
+
+/// The size of a section of a compiled sketch.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Section {
+    name: String,
+    used: u64,
+    max: Option<u64>,
+}
+
+impl Section {
+
+    /// The name of the section, e.g. `text` for program storage space.
+    pub fn name(&self) -> &str { &self.name }
+
+    /// The number of bytes used by the sketch.
+    pub fn used(&self) -> u64 { self.used }
+
+    /// The number of bytes available on the board, if the board's platform specifies it.
+    pub fn max(&self) -> Option<u64> { self.max }
+}
+
+/// The memory usage of a compiled sketch, as reported by the Arduino CLI.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SizeReport {
+    sections: Vec<Section>,
+}
+
+impl SizeReport {
+
+    pub fn sections(&self) -> &[Section] { &self.sections }
+
+    /// The program storage space, which is used by the sketch's code and constants.
+    pub fn flash(&self) -> Option<&Section> { self.section(FLASH_SECTION) }
+
+    /// The dynamic memory, which is used by the sketch's global variables. The remainder is left
+    /// for local variables.
+    pub fn ram(&self) -> Option<&Section> { self.section(RAM_SECTION) }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Checks the usage of every section limited by the given budget.
+    ///
+    /// # Errors
+    /// * `SizeBudgetExceeded`, for the first section which uses more bytes than the budget allows.
+    /// * `SizeNotReported`, for the first section limited by the budget which is not reported.
+    pub fn check(&self, budget: &SizeBudget) -> Result<(), Error> {
+        for (name, limit) in &budget.limits {
+            let section = self.section(name).ok_or_else(|| Error::SizeNotReported(name.clone()))?;
+
+            if section.used > *limit {
+                return Err(Error::SizeBudgetExceeded { section: name.clone(), used: section.used, limit: *limit });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Limits of the memory usage of a compiled sketch, in bytes, which can be lower than the
+/// memory available on the board to catch regressions early.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct SizeBudget {
+    limits: Vec<(String, u64)>,
+}
+
+impl SizeBudget {
+
+    pub fn new() -> SizeBudget { SizeBudget::default() }
+
+    /// Limits the program storage space used by the sketch.
+    pub fn flash(self, limit: u64) -> SizeBudget { self.section(FLASH_SECTION, limit) }
+
+    /// Limits the dynamic memory used by the sketch's global variables.
+    pub fn ram(self, limit: u64) -> SizeBudget { self.section(RAM_SECTION, limit) }
+
+    /// Limits the bytes used by the section with the given name.
+    pub fn section(mut self, name: &str, limit: u64) -> SizeBudget {
+        self.limits.retain(|(section, _)| section != name);
+        self.limits.push((String::from(name), limit));
+        self
+    }
+}
+
+/// The extensions of the binaries produced by compiling a sketch.
+const ARTIFACT_EXTENSIONS: [&str; 3] = ["hex", "bin", "elf"];
+
+/// The result of a successful compilation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CompileOutput {
+    diagnostics: Vec<Diagnostic>,
+    build_path: Option<PathBuf>,
+    artifacts: Vec<PathBuf>,
+    size: Option<SizeReport>,
+}
+
+impl CompileOutput {
+
+    /// The compiler's warnings and notes.
+    pub fn diagnostics(&self) -> &[Diagnostic] { &self.diagnostics }
+
+    /// The directory in which the sketch was built, if it is known.
+    pub fn build_path(&self) -> Option<&Path> { self.build_path.as_deref() }
+
+    /// The memory usage of the compiled sketch. This is only reported by versions of the Arduino
+    /// CLI since 0.14.
+    pub fn size(&self) -> Option<&SizeReport> { self.size.as_ref() }
+
+    /// The paths of all produced `.hex`, `.bin` and `.elf` binaries, in alphabetical order.
+    pub fn artifacts(&self) -> &[PathBuf] { &self.artifacts }
+
+    /// The produced `.hex` binary, if the board's platform produces one. This is the sketch
+    /// alone, without a bootloader.
+    pub fn hex(&self) -> Option<&Path> { self.artifact("hex") }
+
+    /// The produced `.bin` binary, if the board's platform produces one.
+    pub fn bin(&self) -> Option<&Path> { self.artifact("bin") }
+
+    /// The produced `.elf` binary.
+    pub fn elf(&self) -> Option<&Path> { self.artifact("elf") }
+
+    /// The first produced binary with the given extension, other than one containing a bootloader.
+    fn artifact(&self, extension: &str) -> Option<&Path> {
+        self.artifacts
+            .iter()
+            .map(PathBuf::as_path)
+            .filter(|path| path.extension().is_some_and(|path_extension| path_extension == extension))
+            .find(|path| !path.to_string_lossy().contains("with_bootloader"))
+    }
+}
+
+/// Compiles a sketch at a given path, for a given board, returning the compiler's warnings.
 /// The given path should point to the sketch **directory**, not **file**.
 ///
+/// The Arduino CLI disables compiler warnings by default, unless configured otherwise.
+///
 /// # Errors
-/// * `CommandFailure`, if the `arduino-cli` command fails or an error occurs during compilation.
-///   This will definitely occur if the given board has an unknown core.
+/// * `SpawnFailure`, if the `arduino-cli` command can not be run.
+/// * `CompilationFailure`, if the compiler reports errors in the sketch. The failure contains the
+///   compiler's diagnostics.
+/// * `CommandFailure`, if compilation fails otherwise, e.g. because the core is not installed.
+/// * `UnknownCore`, if the given board has an unknown core.
 /// * `InvalidSketchPath`, if the sketch does not have the format required for Arduino sketches.
-pub fn compile(sketch: &Path, board: &Board) -> Result<(), Error> {
-    // Command failure would occur if this device info was used.
-    if board.has_unknown_core() { return Err(Error::CommandFailure); }
-
-    let path = sketch_to_string(sketch)?;
+pub fn compile<T: Target + ?Sized>(sketch: &Path, board: &T) -> Result<Vec<Diagnostic>, Error> {
+    default_client().compile(sketch, board)
+}
 
-    // Asks the Arduino CLI to compile the given sketch.
-    let compilation_result = process::Command::new("arduino-cli")
-        .args(&["compile", "--fqbn", board.fqbn(), &path])
-        .stdout(process::Stdio::null())
-        .stderr(process::Stdio::null())
-        .status();
+/// Compiles a sketch at a given path, for a given board, with the given options, returning the
+/// compiler's warnings and the produced binaries.
+///
+/// The binaries are looked up in the output directory, if one is set, or the build path.
+///
+/// # Errors
+/// * as described for `cli::compile`.
+/// * `SizeBudgetExceeded`, if the options contain a size budget which the compiled sketch
+///   exceeds.
+/// * `SizeNotReported`, if the options contain a size budget, but the Arduino CLI does not report
+///   the memory usage of a section it limits.
+/// * `Timeout` or `Cancelled`, if compilation was stopped because of the options or the client.
+pub fn compile_with<T: Target + ?Sized>(sketch: &Path, board: &T, options: &CompileOptions) -> Result<CompileOutput, Error> {
+    default_client().compile_with(sketch, board, options)
+}
 
-    match compilation_result {
-        Ok(status) if status.success() => Ok(()),
-        _ => Err(Error::CommandFailure),
-    }
+/// Compiles a sketch like `cli::compile_with`, passing each line printed by the Arduino CLI to the
+/// given callback while compiling. If the callback breaks, compilation is stopped.
+///
+/// The Arduino CLI only reports the build path in its JSON output, which is printed at once when
+/// compilation finishes. So the binaries are only found if an output directory or build path is
+/// set, or binaries are exported.
+///
+/// # Errors
+/// * as described for `cli::compile_with`.
+/// * `Cancelled`, if the callback stopped compilation.
+pub fn compile_streaming<T, F>(sketch: &Path, board: &T, options: &CompileOptions, on_event: F) -> Result<CompileOutput, Error>
+where T: Target + ?Sized, F: FnMut(&OutputEvent) -> ControlFlow<()> {
+    default_client().compile_streaming(sketch, board, options, on_event)
 }
 
 /// Uploads a **compiled** sketch onto Arduino with the given board.
 /// The given path should point to the sketch **directory**, not **file**.
 ///
+/// Network boards are uploaded onto over the air, via their address and protocol.
+///
 /// # Errors
-/// * `CommandFailure`, if the `arduino-cli` command fails or an error occurs during uploading.
-///   This will definitely occur if the given board has an unknown core, or its Arduino is not
+/// * `SpawnFailure`, if the `arduino-cli` command can not be run.
+/// * `CommandFailure`, if an error occurs during uploading, e.g. because the Arduino is not
 ///   connected.
+/// * `UnknownCore`, if the given board has an unknown core.
 /// * `InvalidSketchPath`, if the sketch does not have the format required for Arduino sketches.
-pub fn upload(sketch: &Path, board: &Board) -> Result<(), Error> {
+pub fn upload<T: Target + ?Sized>(sketch: &Path, board: &T) -> Result<(), Error> {
+    default_client().upload(sketch, board)
+}
+
+/// Uploads a compiled sketch or prebuilt binaries onto Arduino with the given board, with the
+/// given options.
+///
+/// # Errors
+/// * as described for `cli::upload`.
+/// * `InvalidSketchPath`, if the options specify neither a sketch nor binaries to upload.
+/// * `Timeout` or `Cancelled`, if uploading was stopped because of the options or the client.
+pub fn upload_with<T: Target + ?Sized>(board: &T, options: &UploadOptions) -> Result<(), Error> {
+    default_client().upload_with(board, options)
+}
+
+/// Uploads like `cli::upload_with`, passing each line printed by the Arduino CLI to the given
+/// callback while uploading, along with the upload's progress if the upload tool reports it. If
+/// the callback breaks, uploading is stopped.
+///
+/// # Errors
+/// * as described for `cli::upload_with`.
+/// * `Cancelled`, if the callback stopped uploading.
+pub fn upload_streaming<T, F>(board: &T, options: &UploadOptions, on_event: F) -> Result<(), Error>
+where T: Target + ?Sized, F: FnMut(&OutputEvent) -> ControlFlow<()> {
+    default_client().upload_streaming(board, options, on_event)
+}
+
+/// Burns the bootloader onto the Arduino with the given board, using the given programmer (e.g.
+/// `usbasp` or `arduinoasisp`).
+///
+/// # Errors
+/// * `SpawnFailure`, if the `arduino-cli` command can not be run.
+/// * `CommandFailure`, if an error occurs while burning, e.g. because the programmer is not
+///   connected.
+/// * `UnknownCore`, if the given board has an unknown core.
+pub fn burn_bootloader<T: Target + ?Sized>(board: &T, programmer: &str) -> Result<(), Error> {
+    default_client().burn_bootloader(board, programmer)
+}
+
+impl ArduinoCli {
+
+    /// Compiles a sketch at a given path, for a given board, as with `cli::compile`.
+    pub fn compile<T: Target + ?Sized>(&self, sketch: &Path, board: &T) -> Result<Vec<Diagnostic>, Error> {
+        self.compile_with(sketch, board, &CompileOptions::new()).map(|output| output.diagnostics)
+    }
+
+    /// Compiles a sketch at a given path, for a given board, with the given options, as with
+    /// `cli::compile_with`.
+    pub fn compile_with<T: Target + ?Sized>(
+        &self, sketch: &Path, board: &T, options: &CompileOptions,
+    ) -> Result<CompileOutput, Error> {
+        self.detected_version()?;
+        let output = self.run_limited(compile_command(self, sketch, board, options, true)?, &options.limits);
+        compile_output_from_output(output, sketch, board, options)
+    }
+
+    /// Compiles a sketch, passing the printed lines to the given callback, as with
+    /// `cli::compile_streaming`.
+    pub fn compile_streaming<T, F>(
+        &self, sketch: &Path, board: &T, options: &CompileOptions, mut on_event: F,
+    ) -> Result<CompileOutput, Error>
+    where T: Target + ?Sized, F: FnMut(&OutputEvent) -> ControlFlow<()> {
+        self.detected_version()?;
+        let command = compile_command(self, sketch, board, options, false)?;
+        let output = self.run_streaming(command, &options.limits, false, &mut on_event);
+        compile_output_from_output(output, sketch, board, options)
+    }
+
+    /// Uploads a **compiled** sketch onto Arduino with the given board, as with `cli::upload`.
+    pub fn upload<T: Target + ?Sized>(&self, sketch: &Path, board: &T) -> Result<(), Error> {
+        self.upload_with(board, &UploadOptions::new().sketch(sketch))
+    }
+
+    /// Uploads onto Arduino with the given board, with the given options, as with
+    /// `cli::upload_with`.
+    pub fn upload_with<T: Target + ?Sized>(&self, board: &T, options: &UploadOptions) -> Result<(), Error> {
+        let version = self.detected_version()?;
+        self.run_limited(upload_command(self, board, options, version)?, &options.limits).map(|_| ())
+    }
+
+    /// Uploads onto Arduino with the given board, passing the printed lines to the given
+    /// callback, as with `cli::upload_streaming`.
+    pub fn upload_streaming<T, F>(&self, board: &T, options: &UploadOptions, mut on_event: F) -> Result<(), Error>
+    where T: Target + ?Sized, F: FnMut(&OutputEvent) -> ControlFlow<()> {
+        let version = self.detected_version()?;
+        self.run_streaming(upload_command(self, board, options, version)?, &options.limits, true, &mut on_event).map(|_| ())
+    }
+
+    /// Burns the bootloader onto the Arduino with the given board, as with
+    /// `cli::burn_bootloader`.
+    pub fn burn_bootloader<T: Target + ?Sized>(&self, board: &T, programmer: &str) -> Result<(), Error> {
+        let version = self.detected_version()?;
+        self.run(burn_bootloader_command(self, board, programmer, version)?).map(|_| ())
+    }
+}
+
+/// The command which asks the Arduino CLI to compile the given sketch, printing its result in
+/// JSON format if `json` is set.
+///
+/// The JSON format contains the compiler's output, so it is only printed once compilation
+/// finishes. The text format is printed while compiling instead.
+pub(super) fn compile_command<T: Target + ?Sized>(
+    cli: &ArduinoCli, sketch: &Path, board: &T, options: &CompileOptions, json: bool,
+) -> Result<process::Command, Error> {
     // Command failure would occur if this device info was used.
-    if board.has_unknown_core() { return Err(Error::CommandFailure); }
+    if board.has_unknown_core() { return Err(Error::UnknownCore); }
 
     let path = sketch_to_string(sketch)?;
+    let mut command = cli.command(["compile", "--fqbn", board.fqbn()]);
+
+    if json { command.args(["--format", "json"]); }
+
+    command.args(options.args()).arg(path);
+
+    Ok(command)
+}
+
+/// Converts the output of the compile command into a compile output, or an error containing the
+/// compiler's diagnostics if compilation failed.
+pub(super) fn compile_output_from_output<T: Target + ?Sized>(
+    output: Result<process::Output, Error>, sketch: &Path, board: &T, options: &CompileOptions,
+) -> Result<CompileOutput, Error> {
+    match output {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let diagnostics = compile_diagnostics(&stdout, &String::from_utf8_lossy(&output.stderr));
+            let builder_result = json::from_str(&stdout).ok().and_then(|result: CompileResult| result.builder_result);
+            let (reported_build_path, sections) = match builder_result {
+                Some(result) => (result.build_path, result.executable_sections_size),
+                None => (None, None),
+            };
+
+            let build_path = options.build_path.clone().or(reported_build_path);
+            let size = sections.map(size_report).or_else(|| size_report_from_text(&stdout));
+
+            // A budget fails the compilation if the memory usage is not reported at all.
+            if let Some(budget) = &options.size_budget {
+                size.clone().unwrap_or(SizeReport { sections: vec![] }).check(budget)?;
+            }
+
+            // Exported binaries are placed in a directory named after the board's FQBN.
+            let exported_dir = options.export_binaries
+                .then(|| sketch.join("build").join(board.fqbn().replace(':', ".")));
+            let artifacts_dir = options.output_dir.as_deref()
+                .or(exported_dir.as_deref())
+                .or(build_path.as_deref());
+
+            let artifacts = artifacts_dir.map(artifacts).unwrap_or_default();
+
+            Ok(CompileOutput { diagnostics, build_path, artifacts, size })
+        },
+        Err(Error::CommandFailure(failure)) => {
+            let diagnostics = compile_diagnostics(failure.stdout(), failure.stderr());
+
+            // Without diagnostics, compilation failed before the compiler was run.
+            if diagnostics.is_empty() {
+                Err(Error::CommandFailure(failure))
+            } else {
+                Err(Error::CompilationFailure { failure, diagnostics })
+            }
+        },
+        Err(error) => Err(error),
+    }
+}
+
+/// The diagnostics contained in the given output of the compile command, mapped to the sketch's
+/// `.ino` files.
+fn compile_diagnostics(stdout: &str, stderr: &str) -> Vec<Diagnostic> {
+    let diagnostics = match json::from_str(stdout) {
+        Ok(CompileResult { builder_result: Some(BuilderResult { diagnostics: Some(formats), .. }), .. }) =>
+            diagnostics_from_formats(formats),
+        Ok(CompileResult { compiler_err, .. }) if !compiler_err.is_empty() =>
+            diagnostics_from_gcc_output(&compiler_err),
+        // Some versions print the compiler's output directly, rather than as part of the JSON.
+        _ => diagnostics_from_gcc_output(stderr),
+    };
 
-    // Asks the Arduino CLI to upload the given compiled sketch.
-    let compilation_result = process::Command::new("arduino-cli")
-        .args(&["upload", "--port", board.port(), "--fqbn", board.fqbn(), &path])
-        .stdout(process::Stdio::null())
-        .stderr(process::Stdio::null())
-        .status();
+    diagnostics.into_iter().map(map_to_sketch).collect()
+}
+
+/// Converts the sections printed by the Arduino CLI into a size report.
+fn size_report(sections: Vec<SectionFormat>) -> SizeReport {
+    let sections = sections
+        .into_iter()
+        .map(|section| Section {
+            name: section.name,
+            used: section.size,
+            max: if section.max_size == 0 { None } else { Some(section.max_size) },
+        })
+        .collect();
+
+    SizeReport { sections }
+}
+
+/// Parses the size report contained in the text output of the compile command, e.g. `Sketch uses
+/// 924 bytes (2%) of program storage space. Maximum is 32256 bytes.`
+fn size_report_from_text(stdout: &str) -> Option<SizeReport> {
+    // The number of bytes preceding the first occurrence of " bytes" in the given text.
+    let bytes = |text: &str| text.split_once(" bytes")?.0.rsplit(' ').next()?.parse().ok();
+
+    let sections: Vec<_> = stdout
+        .lines()
+        .filter_map(|line| {
+            let name = if line.contains("of program storage space") {
+                FLASH_SECTION
+            } else if line.contains("of dynamic memory") {
+                RAM_SECTION
+            } else {
+                return None;
+            };
+
+            let max = line.split_once("Maximum is ").and_then(|(_, maximum)| bytes(maximum));
+            Some(Section { name: String::from(name), used: bytes(line)?, max })
+        })
+        .collect();
+
+    if sections.is_empty() { None } else { Some(SizeReport { sections }) }
+}
+
+/// The binaries in the given directory, in alphabetical order.
+fn artifacts(directory: &Path) -> Vec<PathBuf> {
+    let mut artifacts: Vec<PathBuf> = fs::read_dir(directory)
+        .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+
+    artifacts.retain(|path| {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        path.is_file() && extension.is_some_and(|extension| ARTIFACT_EXTENSIONS.contains(&extension))
+    });
+    artifacts.sort();
+
+    artifacts
+}
+
+/// The command which asks the Arduino CLI to upload onto the given board with the given options.
+pub(super) fn upload_command<T: Target + ?Sized>(
+    cli: &ArduinoCli, board: &T, options: &UploadOptions, version: Version,
+) -> Result<process::Command, Error> {
+    // Without any input, the Arduino CLI would upload the sketch in the working directory.
+    if !options.has_input() { return Err(Error::InvalidSketchPath); }
+
+    let path = options.sketch.as_deref().map(sketch_to_string).transpose()?;
+    let mut command = board_command(cli, "upload", board, version)?;
+    command.args(options.args(version)).args(path);
 
-    match compilation_result {
-        Ok(status) if status.success() => Ok(()),
-        _ => Err(Error::CommandFailure),
+    Ok(command)
+}
+
+/// The command which asks the Arduino CLI to burn the bootloader onto the given board.
+pub(super) fn burn_bootloader_command<T: Target + ?Sized>(
+    cli: &ArduinoCli, board: &T, programmer: &str, version: Version,
+) -> Result<process::Command, Error> {
+    let mut command = board_command(cli, "burn-bootloader", board, version)?;
+    command.args(["--programmer", programmer]);
+
+    Ok(command)
+}
+
+/// A command which asks the Arduino CLI to perform the given subcommand on the given board.
+fn board_command<T: Target + ?Sized>(
+    cli: &ArduinoCli, subcommand: &str, board: &T, version: Version,
+) -> Result<process::Command, Error> {
+    // Command failure would occur if this device info was used.
+    if board.has_unknown_core() { return Err(Error::UnknownCore); }
+
+    let mut args = vec![subcommand, "--port", board.address(), "--fqbn", board.fqbn()];
+
+    // Serial ports are the default, so a protocol is only required for network boards. Versions
+    // without pluggable discovery infer the protocol from the address.
+    if board.protocol() != "serial" && version >= PLUGGABLE_DISCOVERY_VERSION {
+        args.extend(["--protocol", board.protocol()]);
     }
+
+    Ok(cli.command(args))
 }
 
 /// Converts a given sketch-path to its canonical string representation, while validating it in the
@@ -92,6 +544,280 @@ fn sketch_to_string(sketch: &Path) -> Result<String, Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::{FakeRunner, ListedBoard};
+
+    /// Creates an empty sketch with the given name in the temporary directory.
+    fn temporary_sketch(name: &str) -> std::path::PathBuf {
+        let sketch = std::env::temp_dir().join(format!("{}{}", name, std::process::id()));
+        let file_name = format!("{}.ino", sketch.file_name().unwrap().to_str().unwrap());
+
+        fs::create_dir_all(&sketch).unwrap();
+        fs::write(sketch.join(file_name), "").unwrap();
+
+        sketch
+    }
+
+    /// An Arduino Uno connected via serial.
+    fn uno() -> ListedBoard {
+        ListedBoard::Serial(serde_json::from_str(r#"{"name": "Arduino Uno", "fqbn": "arduino:avr:uno", "port": "/dev/ttyACM0", "usbID": ""}"#).unwrap())
+    }
+
+    /// Uploads onto the only network board listed by a fake Arduino CLI with the given version,
+    /// returning the arguments of the upload command.
+    fn upload_onto_network_board(version: &str) -> Vec<String> {
+        let board_list = r#"[{"address": "192.168.1.17", "protocol": "network", "boards": [{"name": "Arduino Yún", "fqbn": "arduino:avr:yun"}]}]"#;
+        let runner = FakeRunner::new()
+            .respond_version(version)
+            .respond(&["board", "list"], board_list, "", 0)
+            .respond(&["upload"], "", "", 0);
+        let cli = ArduinoCli::new().runner(runner.clone());
+        let sketch = temporary_sketch(&format!("upload{}", version.replace('.', "")));
+
+        let board: ListedBoard = cli.board_list().unwrap().remove(0);
+        cli.upload(&sketch, &board).unwrap();
+        fs::remove_dir_all(&sketch).unwrap();
+
+        runner.commands().pop().unwrap()
+    }
+
+    #[test]
+    fn network_upload() {
+        let with_discovery = upload_onto_network_board("0.19.0");
+        let without_discovery = upload_onto_network_board("0.13.0");
+
+        assert_eq!(with_discovery[..7], ["upload", "--port", "192.168.1.17", "--fqbn", "arduino:avr:yun", "--protocol", "network"]);
+        assert!(!without_discovery.contains(&String::from("--protocol")));
+    }
+
+    #[test]
+    fn upload_prebuilt_binary() {
+        let runner = FakeRunner::new().respond_version("1.0.4").respond(&["upload"], "", "", 0);
+        let cli = ArduinoCli::new().runner(runner.clone());
+        let options = UploadOptions::new().input_file("/releases/Blink.ino.hex");
+
+        cli.upload_with(&uno(), &options).unwrap();
+
+        assert_eq!(runner.commands().pop().unwrap(), [
+            "upload", "--port", "/dev/ttyACM0", "--fqbn", "arduino:avr:uno", "--input-file", "/releases/Blink.ino.hex",
+        ]);
+        assert_eq!(cli.upload_with(&uno(), &UploadOptions::new().verify(true)), Err(Error::InvalidSketchPath));
+    }
+
+    #[test]
+    fn burn_bootloader() {
+        let runner = FakeRunner::new().respond_version("1.0.4").respond(&["burn-bootloader"], "", "", 0);
+        let cli = ArduinoCli::new().runner(runner.clone());
+
+        cli.burn_bootloader(&uno(), "usbasp").unwrap();
+
+        assert_eq!(runner.commands().pop().unwrap(), [
+            "burn-bootloader", "--port", "/dev/ttyACM0", "--fqbn", "arduino:avr:uno", "--programmer", "usbasp",
+        ]);
+    }
+
+    #[test]
+    fn failed_compilation() {
+        let runner = FakeRunner::new()
+            .respond_version("1.0.4")
+            .respond(&["compile"], "", "error: expected ';' before '}' token", 1);
+        let cli = ArduinoCli::new().runner(runner);
+        let sketch = temporary_sketch("failure");
+
+        let result = cli.compile(&sketch, &uno());
+        fs::remove_dir_all(&sketch).unwrap();
+
+        match result {
+            Err(Error::CommandFailure(failure)) => {
+                assert_eq!(failure.exit_code(), Some(1));
+                assert_eq!(failure.stderr(), "error: expected ';' before '}' token");
+            },
+            _ => panic!("expected a command failure, got {:?}", result),
+        }
+    }
+
+    /// The errors and warnings contained in the compile fixtures.
+    fn fixture_diagnostics() -> (String, String) {
+        (
+            String::from("/home/user/Arduino/Blink/Blink.ino:9:1: error: expected ';' before '}' token"),
+            String::from("/home/user/Arduino/Blink/Blink.ino:7:7: warning: unused variable 'count' [-Wunused-variable]"),
+        )
+    }
+
+    #[test]
+    fn fixture_0_21() {
+        let diagnostics = compile_diagnostics(include_str!("fixtures/compile_0.21.json"), "");
+        let formatted: Vec<_> = diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect();
+
+        let (error, warning) = fixture_diagnostics();
+        assert_eq!(formatted, [error, warning]);
+    }
+
+    #[test]
+    fn fixture_1_0() {
+        let diagnostics = compile_diagnostics(include_str!("fixtures/compile_1.0.json"), "");
+        let formatted: Vec<_> = diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect();
+
+        let (error, warning) = fixture_diagnostics();
+        assert_eq!(formatted, [error, warning]);
+    }
+
+    #[test]
+    fn unwrapped_compiler_output() {
+        let stderr = "/home/user/Arduino/Blink/Blink.ino:9:1: error: expected ';' before '}' token\n";
+
+        let diagnostics = compile_diagnostics("", stderr);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line(), diagnostics[0].column()), (9, Some(1)));
+    }
+
+    #[test]
+    fn compilation_diagnostics() {
+        let runner = FakeRunner::new()
+            .respond_version("1.0.4")
+            .respond(&["compile"], include_str!("fixtures/compile_1.0.json"), "", 1);
+        let cli = ArduinoCli::new().runner(runner);
+        let sketch = temporary_sketch("diagnostics");
+
+        let result = cli.compile(&sketch, &uno());
+        fs::remove_dir_all(&sketch).unwrap();
+
+        let error = result.unwrap_err();
+        let (expected_error, _) = fixture_diagnostics();
+        assert!(matches!(error, Error::CompilationFailure { ref diagnostics, .. } if diagnostics.len() == 2));
+        assert!(error.to_string().ends_with(&format!("failed to compile the sketch:\n{}", expected_error)));
+    }
+
+    #[test]
+    fn warnings_on_success() {
+        let (_, warning) = fixture_diagnostics();
+        let compile_json = json::json!({"compiler_err": warning, "success": true}).to_string();
+        let runner = FakeRunner::new()
+            .respond_version("0.21.1")
+            .respond(&["compile"], &compile_json, "", 0);
+        let cli = ArduinoCli::new().runner(runner);
+        let sketch = temporary_sketch("warnings");
+
+        let result = cli.compile(&sketch, &uno());
+        fs::remove_dir_all(&sketch).unwrap();
+
+        let diagnostics = result.unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity(), crate::cli::Severity::Warning);
+    }
+
+    #[test]
+    fn compile_artifacts() {
+        let output_dir = std::env::temp_dir().join(format!("output{}", std::process::id()));
+        fs::create_dir_all(&output_dir).unwrap();
+        for file in ["Blink.ino.eep", "Blink.ino.elf", "Blink.ino.hex", "Blink.ino.with_bootloader.hex"] {
+            fs::write(output_dir.join(file), "").unwrap();
+        }
+
+        let runner = FakeRunner::new()
+            .respond_version("1.0.4")
+            .respond(&["compile"], r#"{"builder_result": {"build_path": "/tmp/build"}, "success": true}"#, "", 0);
+        let cli = ArduinoCli::new().runner(runner.clone());
+        let sketch = temporary_sketch("artifacts");
+        let options = CompileOptions::new().output_dir(&output_dir).optimize_for_debug(true);
+
+        let result = cli.compile_with(&sketch, &uno(), &options);
+        fs::remove_dir_all(&sketch).unwrap();
+        fs::remove_dir_all(&output_dir).unwrap();
+
+        let output = result.unwrap();
+        assert_eq!(output.build_path(), Some(Path::new("/tmp/build")));
+        assert_eq!(output.artifacts().len(), 3);
+        assert_eq!(output.hex(), Some(output_dir.join("Blink.ino.hex").as_path()));
+        assert_eq!(output.elf(), Some(output_dir.join("Blink.ino.elf").as_path()));
+        assert_eq!(output.bin(), None);
+
+        let args = runner.commands().pop().unwrap();
+        assert_eq!(args[..5], ["compile", "--fqbn", "arduino:avr:uno", "--format", "json"]);
+        assert_eq!(args[5..8], ["--output-dir", &output_dir.to_string_lossy(), "--optimize-for-debug"]);
+    }
+
+    /// Compiles for an Arduino Uno with a fake Arduino CLI reporting the memory usage of the
+    /// fixture, using the given options and a temporary sketch with the given name.
+    fn compile_fixture_size(name: &str, options: &CompileOptions) -> Result<CompileOutput, Error> {
+        let runner = FakeRunner::new()
+            .respond_version("1.0.4")
+            .respond(&["compile"], include_str!("fixtures/compile_size_1.0.json"), "", 0);
+        let cli = ArduinoCli::new().runner(runner);
+        let sketch = temporary_sketch(name);
+
+        let result = cli.compile_with(&sketch, &uno(), options);
+        fs::remove_dir_all(&sketch).unwrap();
+
+        result
+    }
+
+    #[test]
+    fn size_report() {
+        let output = compile_fixture_size("size", &CompileOptions::new()).unwrap();
+        let size = output.size().unwrap();
+
+        assert_eq!(size.sections().len(), 2);
+        assert_eq!(size.flash(), Some(&Section { name: String::from("text"), used: 924, max: Some(32256) }));
+        assert_eq!(size.ram().map(|ram| (ram.used(), ram.max())), Some((9, Some(2048))));
+    }
+
+    #[test]
+    fn text_size_report() {
+        let stdout = "Sketch uses 924 bytes (2%) of program storage space. Maximum is 32256 bytes.\n\
+            Global variables use 9 bytes (0%) of dynamic memory, leaving 2039 bytes for local variables. Maximum is 2048 bytes.\n";
+
+        let size = size_report_from_text(stdout).unwrap();
+
+        assert_eq!(size, compile_fixture_size("text", &CompileOptions::new()).unwrap().size().unwrap().clone());
+        assert_eq!(size_report_from_text("Sketch uses 10396 bytes of program storage space.").unwrap().flash().unwrap().max(), None);
+        assert_eq!(size_report_from_text(""), None);
+    }
+
+    #[test]
+    fn size_budget() {
+        let within = CompileOptions::new().size_budget(SizeBudget::new().flash(1024).ram(9));
+        let exceeded = CompileOptions::new().size_budget(SizeBudget::new().flash(1024).ram(8));
+
+        assert!(compile_fixture_size("within", &within).is_ok());
+        assert_eq!(
+            compile_fixture_size("exceeded", &exceeded),
+            Err(Error::SizeBudgetExceeded { section: String::from("data"), used: 9, limit: 8 }),
+        );
+    }
+
+    #[test]
+    fn unreported_size_budget() {
+        let budget = CompileOptions::new().size_budget(SizeBudget::new().flash(1024).section("bss", 512));
+
+        assert_eq!(compile_fixture_size("unreported", &budget), Err(Error::SizeNotReported(String::from("bss"))));
+
+        let runner = FakeRunner::new()
+            .respond_version("1.0.4")
+            .respond(&["compile"], r#"{"builder_result": {"build_path": "/tmp/build"}, "success": true}"#, "", 0);
+        let cli = ArduinoCli::new().runner(runner);
+        let sketch = temporary_sketch("sizeless");
+
+        let result = cli.compile_with(&sketch, &uno(), &budget);
+        fs::remove_dir_all(&sketch).unwrap();
+
+        assert_eq!(result, Err(Error::SizeNotReported(String::from("text"))));
+    }
+
+    #[test]
+    fn generated_source_diagnostics() {
+        let build = std::env::temp_dir().join(format!("generated{}", std::process::id()));
+        let generated = build.join("Blink.ino.cpp");
+        fs::create_dir_all(&build).unwrap();
+        fs::write(&generated, "#include <Arduino.h>\n#line 1 \"/home/user/Arduino/Blink/Blink.ino\"\nvoid setup();\n").unwrap();
+        let stderr = format!("{}:3:6: warning: unused parameter 'pin'", generated.display());
+
+        let diagnostics = compile_diagnostics("", &stderr);
+        fs::remove_dir_all(&build).unwrap();
+
+        assert_eq!(diagnostics[0].file(), Path::new("/home/user/Arduino/Blink/Blink.ino"));
+        assert_eq!(diagnostics[0].line(), 1);
+    }
 
     #[test]
     fn invalid_sketch_path_to_str() {
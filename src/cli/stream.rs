@@ -0,0 +1,299 @@
+use std::io::{self, Read};
+use std::ops::ControlFlow;
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc::{self, Sender, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long a running command may print nothing before a runner reports that it is idle.
+pub(super) const IDLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An output stream of the Arduino CLI.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// What a runner reports about a running command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Activity<'a> {
+    /// The command printed the given line to the given stream.
+    Line(Stream, &'a str),
+    /// The command has not printed anything for a while, but is still running.
+    Idle,
+}
+
+/// A line printed by the Arduino CLI while it is running.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OutputEvent {
+    pub(super) stream: Stream,
+    pub(super) line: String,
+    pub(super) percentage: Option<f32>,
+}
+
+impl OutputEvent {
+
+    pub fn stream(&self) -> Stream { self.stream }
+
+    /// The printed line, without its line terminator.
+    pub fn line(&self) -> &str { &self.line }
+
+    /// The progress of a download or upload between 0 and 100, if the line reports one.
+    pub fn percentage(&self) -> Option<f32> { self.percentage }
+}
+
+/// Spawns the given command and passes each line it prints to the given callback, as it is
+/// printed. The callback is also called whenever the command has been idle for `IDLE_INTERVAL`.
+/// If the callback breaks, the command and all processes it started are killed.
+///
+/// Lines are terminated by line feeds or carriage returns, as the Arduino CLI and the upload tools
+/// redraw progress bars using the latter.
+///
+/// # Errors
+/// * if the command can not be spawned, or can not be killed once the callback breaks. In the
+///   latter case, the command or the processes it started may still be running.
+pub(super) fn spawn_streaming(
+    mut command: Command, on_activity: &mut dyn FnMut(Activity) -> ControlFlow<()>,
+) -> io::Result<Output> {
+    lead_process_group(&mut command);
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().map(|stdout| read_lines(stdout, Stream::Stdout, sender.clone()));
+    let stderr = child.stderr.take().map(|stderr| read_lines(stderr, Stream::Stderr, sender));
+
+    let mut are_streams_closed = false;
+
+    let status = loop {
+        let flow = if are_streams_closed {
+            // The command may keep running after closing its streams, so it is still watched
+            // until it exits.
+            if let Some(status) = child.try_wait()? { break status; }
+
+            thread::sleep(IDLE_INTERVAL);
+            on_activity(Activity::Idle)
+        } else {
+            match receiver.recv_timeout(IDLE_INTERVAL) {
+                Ok((stream, line)) => on_activity(Activity::Line(stream, &line)),
+                Err(RecvTimeoutError::Timeout) => on_activity(Activity::Idle),
+                // The receiver is disconnected once both streams are closed.
+                Err(RecvTimeoutError::Disconnected) => {
+                    are_streams_closed = true;
+                    continue;
+                },
+            }
+        };
+
+        if flow.is_break() {
+            kill_process_tree(child.id(), || child.kill())?;
+            break child.wait()?;
+        }
+    };
+
+    let collect = |reader: Option<JoinHandle<Vec<u8>>>| reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+
+    Ok(Output { status, stdout: collect(stdout), stderr: collect(stderr) })
+}
+
+/// Makes the given command lead a new process group, so that the processes it starts can be
+/// killed along with it (see `kill_process_tree`).
+pub(super) fn lead_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// Kills the child with the given ID along with all processes it started, where the child was
+/// spawned by a command leading a new process group. Otherwise the processes started by the child
+/// (e.g. upload tools) would keep running, and keep its output streams open.
+///
+/// The given function kills only the child itself. It is called in addition on Unix, and instead
+/// on other platforms if the process tree can not be killed.
+///
+/// # Errors
+/// * if the process group can not be killed on Unix, or the child can not be killed elsewhere.
+pub(super) fn kill_process_tree<F: FnOnce() -> io::Result<()>>(id: u32, kill_child: F) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        // The child leads the process group, so the group's ID is the child's. A process group
+        // which no longer exists has nothing left to kill.
+        let killed = match unsafe { libc::killpg(id as libc::pid_t, libc::SIGKILL) } {
+            0 => Ok(()),
+            _ => match io::Error::last_os_error() {
+                error if error.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+                error => Err(error),
+            },
+        };
+
+        kill_child().and(killed)
+    }
+
+    #[cfg(windows)]
+    {
+        let status = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &id.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            _ => kill_child(),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = id;
+        kill_child()
+    }
+}
+
+/// Reads the given stream on a separate thread, sending each line it contains. The thread returns
+/// everything it has read once the stream is closed.
+fn read_lines<R: Read + Send + 'static>(mut reader: R, stream: Stream, sender: Sender<(Stream, String)>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = vec![];
+        let mut line = vec![];
+        let mut buffer = [0; 1024];
+
+        loop {
+            let count = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(count) => count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+
+            for &byte in &buffer[..count] {
+                if byte != b'\n' && byte != b'\r' {
+                    line.push(byte);
+                } else if !line.is_empty() {
+                    // The receiver stops listening if the command is killed, which is not an error.
+                    let _ = sender.send((stream, String::from_utf8_lossy(&line).into_owned()));
+                    line.clear();
+                }
+            }
+
+            output.extend_from_slice(&buffer[..count]);
+        }
+
+        if !line.is_empty() {
+            let _ = sender.send((stream, String::from_utf8_lossy(&line).into_owned()));
+        }
+
+        output
+    })
+}
+
+/// The non-empty lines contained in the given output, terminated by line feeds or carriage
+/// returns.
+pub(super) fn output_lines(output: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(output)
+        .split(['\n', '\r'])
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses the last percentage in the given line, e.g. `37.30` in the download progress bar
+/// `arduino:avr-gcc@7.3.0 12.00 MiB / 32.17 MiB  37.30% 00m03s`.
+pub(super) fn percentage(line: &str) -> Option<f32> {
+    line.rmatch_indices('%')
+        .filter_map(|(index, _)| {
+            // Some upload tools separate the number from the percent sign, e.g. `(50 %)`.
+            let number = line[..index].trim_end();
+            let start = number
+                .char_indices()
+                .rev()
+                .find(|(_, character)| !character.is_ascii_digit() && *character != '.')
+                .map_or(0, |(start, character)| start + character.len_utf8());
+
+            number[start..].parse::<f32>().ok()
+        })
+        .find(|percentage| (0.0..=100.0).contains(percentage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentages() {
+        assert_eq!(percentage("arduino:avr-gcc@7.3.0-atmel3.6.1-arduino7 12.00 MiB / 32.17 MiB   37.30% 00m03s"), Some(37.3));
+        assert_eq!(percentage("Writing | ################################################## | 100% 0.25s"), Some(100.0));
+        assert_eq!(percentage("Writing at 0x00010000... (50 %)"), Some(50.0));
+        assert_eq!(percentage("[==============================] 100% (42/42 pages)"), Some(100.0));
+        assert_eq!(percentage("Platform arduino:avr@1.8.6 installed"), None);
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(output_lines(b"Downloading 10%\rDownloading 100%\r\nInstalled\n"), ["Downloading 10%", "Downloading 100%", "Installed"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn streaming() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo one; echo two >&2; echo three"]);
+        let mut lines = vec![];
+
+        let output = spawn_streaming(command, &mut |activity| {
+            if let Activity::Line(stream, line) = activity { lines.push((stream, String::from(line))); }
+            ControlFlow::Continue(())
+        }).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"one\nthree\n");
+        assert!(lines.contains(&(Stream::Stderr, String::from("two"))));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancelled_streaming() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo started; exec sleep 10"]);
+
+        let output = spawn_streaming(command, &mut |activity| {
+            if activity == Activity::Idle { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }).unwrap();
+
+        assert_eq!(output.status.code(), None);
+        assert_eq!(output.stdout, b"started\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancelled_after_closing_streams() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "exec >&- 2>&-; sleep 10"]);
+        let start = std::time::Instant::now();
+
+        let output = spawn_streaming(command, &mut |activity| {
+            let is_late = start.elapsed() > Duration::from_millis(200);
+            if activity == Activity::Idle && is_late { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }).unwrap();
+
+        assert_eq!(output.status.code(), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn killed_process_tree() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 10 & sleep 10"]);
+        let start = std::time::Instant::now();
+
+        // The background process would keep stdout open after its parent was killed.
+        let output = spawn_streaming(command, &mut |_| ControlFlow::Break(())).unwrap();
+
+        assert_eq!(output.status.code(), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
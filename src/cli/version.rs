@@ -0,0 +1,161 @@
+use std::str;
+use std::fmt;
+use std::process::{Command, Output};
+use serde::Deserialize;
+use serde_json as json;
+
+use super::Error;
+use super::client::{ArduinoCli, default_client};
+
+/// The oldest version of the Arduino CLI whose commands and output formats are supported.
+pub const MINIMUM_SUPPORTED_VERSION: Version = Version::new(0, 3, 0);
+
+/// The first major version of the Arduino CLI whose output formats are not known, and which is
+/// therefore not supported.
+const FIRST_UNSUPPORTED_MAJOR_VERSION: u32 = 2;
+
+/// Since this version, `board list` prints a list of detected ports, and `core search` prints a
+/// bare list of cores.
+pub(super) const DETECTED_PORTS_VERSION: Version = Version::new(0, 11, 0);
+
+/// Since this version, ports are discovered by pluggable discovery tools, so uploading onto a
+/// network board requires its protocol.
+pub(super) const PLUGGABLE_DISCOVERY_VERSION: Version = Version::new(0, 19, 0);
+
+/// Since this version, all JSON output is wrapped in an object.
+pub(super) const WRAPPED_OUTPUT_VERSION: Version = Version::new(0, 36, 0);
+
+/// A wrapper for the result of calling `arduino-cli version --format json`.
+#[derive(Deserialize)]
+struct VersionInfo {
+    #[serde(rename = "VersionString")]
+    version_string: String,
+}
+
+/// A version of the Arduino CLI.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Version {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl Version {
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version { major, minor, patch }
+    }
+
+    pub fn major(&self) -> u32 { self.major }
+
+    pub fn minor(&self) -> u32 { self.minor }
+
+    pub fn patch(&self) -> u32 { self.patch }
+
+    /// Indicates whether the commands and output formats of this version are supported by the
+    /// `cli` module.
+    pub fn is_supported(&self) -> bool {
+        *self >= MINIMUM_SUPPORTED_VERSION && self.major < FIRST_UNSUPPORTED_MAJOR_VERSION
+    }
+
+    /// Parses a version string as printed by the Arduino CLI (e.g. `0.35.3` or
+    /// `0.3.6-alpha.preview`), ignoring any pre-release or build suffix.
+    fn parse(version_string: &str) -> Option<Version> {
+        let core = version_string.split(['-', '+']).next()?;
+        let mut components = core.split('.').map(|component| component.parse().ok());
+
+        let major = components.next()??;
+        let minor = components.next()??;
+        let patch = components.next().unwrap_or(Some(0))?;
+
+        if components.next().is_some() { return None; }
+
+        Some(Version::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for Version {
+
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Calls `arduino-cli version` and parses the resulting version.
+///
+/// This does not check whether the version is supported (see `Version::is_supported`).
+///
+/// # Errors
+/// * `SpawnFailure` or `CommandFailure`, if the `arduino-cli` command can not be run or fails.
+/// * `UnknownFormat`, if the output or the version string has an unexpected format. This occurs
+///   for development builds, which are not versioned.
+pub fn version() -> Result<Version, Error> {
+    default_client().version()
+}
+
+impl ArduinoCli {
+
+    /// Calls `arduino-cli version` and parses the resulting version, as with `cli::version`.
+    pub fn version(&self) -> Result<Version, Error> {
+        version_from_output(self.run(version_command(self)))
+    }
+}
+
+/// Returns the given version, if it is supported.
+pub(super) fn check_supported(version: Version) -> Result<Version, Error> {
+    if version.is_supported() { Ok(version) } else { Err(Error::UnsupportedVersion(version)) }
+}
+
+/// The command which asks the Arduino CLI for its version in JSON format.
+pub(super) fn version_command(cli: &ArduinoCli) -> Command {
+    cli.command(["version", "--format", "json"])
+}
+
+/// Converts the output of the version command into a version.
+pub(super) fn version_from_output(output: Result<Output, Error>) -> Result<Version, Error> {
+    // The command line output has to be converted to a valid UTF-8 string before being able to
+    // use it.
+    str::from_utf8(&output?.stdout)
+        .map_err(|_| Error::UnknownFormat)
+        .and_then(version_from_json)
+}
+
+fn version_from_json(version_json: &str) -> Result<Version, Error> {
+    json::from_str(version_json)
+        .ok()
+        .and_then(|info: VersionInfo| Version::parse(&info.version_string))
+        .ok_or(Error::UnknownFormat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_strings() {
+        assert_eq!(Version::parse("0.35.3"), Some(Version::new(0, 35, 3)));
+        assert_eq!(Version::parse("0.3.6-alpha.preview"), Some(Version::new(0, 3, 6)));
+        assert_eq!(Version::parse("1.0.0-rc1+build"), Some(Version::new(1, 0, 0)));
+        assert_eq!(Version::parse("git-snapshot"), None);
+        assert_eq!(Version::parse("0.35.3.1"), None);
+    }
+
+    #[test]
+    fn version_json() {
+        let version_json = r#"{"Application": "arduino-cli", "VersionString": "1.0.4", "Commit": "a0d912da", "Status": "", "Date": "2024-08-12T13:42:52Z"}"#;
+
+        assert_eq!(version_from_json(version_json), Ok(Version::new(1, 0, 4)));
+        assert_eq!(version_from_json(r#"{"VersionString": "nightly-20240101"}"#), Err(Error::UnknownFormat));
+        assert_eq!(version_from_json(""), Err(Error::UnknownFormat));
+    }
+
+    #[test]
+    fn supported_versions() {
+        assert!(Version::new(0, 3, 6).is_supported());
+        assert!(Version::new(1, 1, 0).is_supported());
+        assert!(!Version::new(0, 2, 2).is_supported());
+        assert!(!Version::new(2, 0, 0).is_supported());
+
+        assert_eq!(check_supported(Version::new(2, 0, 0)), Err(Error::UnsupportedVersion(Version::new(2, 0, 0))));
+    }
+}
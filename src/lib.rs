@@ -2,12 +2,19 @@
 //! This library provides an interface for working with Arduino-related tasks.
 //! It provides a Firmata-based interface for manipulating Arduino boards, as well as an interface
 //! for working with the Arduino CLI.
+//! The underlying Firmata messages can be encoded and decoded directly via the `firmata` module,
+//! and a virtual Arduino for testing without hardware is provided by the `sim` module.
 //!
 //! # Expectations
 //! * the Arduino CLI is installed and accessible using the `arduino-cli` command.
 //! * there is exactly one Arduino connected to the computer.
 //!
 //! Not meeting these expectations will result in errors for almost all function/method calls.
+//!
+//! # Features
+//! * `embedded-hal`: implements the `embedded-hal` traits for an Arduino's pins and buses (see
+//!   the `hal` module).
+//! * `async`: provides `AsyncArduino` and the `cli::nonblocking` module, for use with tokio.
 
 mod arduino;
 pub use arduino::*;